@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use bevy_defer::{AsyncAccess, AsyncWorld};
+use bevy_defer::AsyncWorld;
 use bevy_easy_database::*;
 use bevy_webgate::prelude::*;
 use maud::{html, Markup, DOCTYPE};
@@ -69,18 +69,11 @@ async fn index() -> axum::response::Html<String> {
 }
 
 // List all players
-async fn list_players() -> axum::response::Html<String> {
-    let query = AsyncWorld.query::<(&Player, &Score)>();
-    let players = query
-        .get_mut(|mut query| -> Vec<(Player, Score)> {
-            let mut players = vec![];
-            for (player, score) in query.iter() {
-                players.push((player.clone(), score.clone()));
-            }
-            players
-        })
-        .unwrap();
+async fn list_players(players: WebQuery<(&Player, &Score)>) -> axum::response::Html<String> {
+    render_players(players.into_inner())
+}
 
+fn render_players(players: Vec<(Player, Score)>) -> axum::response::Html<String> {
     let markup = html! {
         div class="player-list" {
             @for (player, score) in players {
@@ -97,8 +90,15 @@ async fn create_player(form: axum::Form<PlayerForm>) -> axum::response::Html<Str
     AsyncWorld.spawn_bundle((Player(form.name.clone()), Score(0)));
     // yielding so the time we come back we'll have the player spawned in
     AsyncWorld.yield_now().await;
-    list_players().await
     // Return updated player list
+    let players = AsyncWorld.run(|world| {
+        world
+            .query::<(&Player, &Score)>()
+            .iter(world)
+            .map(|(player, score)| (player.clone(), score.clone()))
+            .collect()
+    });
+    render_players(players)
 }
 
 fn update_from_player(player: &Player, score: &Score) -> Markup {
@@ -1,13 +1,14 @@
 use axum::{
     extract::Path,
-    http::StatusCode,
+    http::HeaderMap,
     response::{IntoResponse, Response},
     routing::get,
     Json,
 };
 use bevy::prelude::*;
-use bevy_defer::{AsyncAccess, AsyncWorld};
-use bevy_webgate::{serve_file, HttpErrorResponses, RouterAppExt, WebServerConfig};
+use bevy_webgate::{
+    embed_dir, serve_file, serve_file_for, RouterAppExt, WebServerAppExt, WebServerConfig,
+};
 use serde_json::{json, Value};
 use std::net::{IpAddr, Ipv4Addr};
 
@@ -37,34 +38,28 @@ fn main() {
             "/",
             get(|| async { serve_file("examples/file_server_assets/index.html").await }),
         )
-        // Serve static files using library utilities
-        .route("/static/{*path}", get(serve_static_file))
+        // Serve static files from a root directory, resolved relative to the crate
+        .port_static(8080, "/static", "examples/file_server_assets")
+        // The same files compiled into the binary, read from disk in debug builds
+        .port_static(
+            8080,
+            "/embedded",
+            embed_dir!("examples/file_server_assets").with_disk_in_debug(true),
+        )
         // Custom file serving example (for demonstration)
         .route("/custom/{*path}", get(serve_custom_file))
         // API endpoint to demonstrate JSON serving
         .route("/api/info", get(serve_api_info))
-        // Fallback for any other routes
-        .fallback(|| async {
-            AsyncWorld
-                .resource::<HttpErrorResponses>()
-                .get(|errors| errors.create_response(StatusCode::NOT_FOUND))
-                .unwrap_or_else(|_| {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Service temporarily unavailable",
-                    )
-                        .into_response()
-                })
-        })
         .run();
 }
 
-async fn serve_static_file(Path(file_path): Path<String>) -> Response {
-    serve_file(&format!("examples/file_server_assets/{}", file_path)).await
-}
-
-async fn serve_custom_file(Path(file_path): Path<String>) -> Response {
-    serve_file(&format!("examples/file_server_assets/{}", file_path)).await
+async fn serve_custom_file(Path(file_path): Path<String>, headers: HeaderMap) -> Response {
+    // Passing the request headers lets browsers revalidate with a 304
+    serve_file_for(
+        &format!("examples/file_server_assets/{}", file_path),
+        &headers,
+    )
+    .await
 }
 
 async fn serve_api_info() -> impl IntoResponse {
@@ -73,7 +68,8 @@ async fn serve_api_info() -> impl IntoResponse {
     let description = "A static file server built with Bevy and Axum";
     let endpoints = vec![
         ("/", "Main index page"),
-        ("/static/*", "Static file serving (library utilities)"),
+        ("/static/*", "Static file serving from a root directory"),
+        ("/embedded/*", "Static files compiled into the binary"),
         ("/custom/*", "Custom file serving (library utilities)"),
         ("/api/info", "This API information"),
     ];
@@ -49,13 +49,16 @@ async fn hello_handler() -> axum::response::Html<&'static str> {
 }
 
 async fn slow_handler() -> axum::response::Html<&'static str> {
-    // Simulate a slow operation using std::thread::sleep (blocking but simple)
+    // Simulate a slow blocking operation, off the executor that serves connections
     println!("Slow request started, will take 5 seconds...");
 
-    for i in 1..=5 {
-        std::thread::sleep(Duration::from_secs(1));
-        println!("Slow request progress: {}s", i);
-    }
+    spawn_async_compute(|| {
+        for i in 1..=5 {
+            std::thread::sleep(Duration::from_secs(1));
+            println!("Slow request progress: {}s", i);
+        }
+    })
+    .await;
 
     println!("Slow request completed!");
     axum::response::Html("<h1>Slow Response</h1><p>This response took 5 seconds to generate.</p>")
@@ -0,0 +1,88 @@
+//! Compares response throughput of a server doing its I/O on the app's executor with one
+//! using dedicated I/O threads, while the app runs at a modest 30 updates per second.
+//!
+//! Run with `cargo run --release --example io_benchmark`.
+
+use axum::body::Bytes;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use bevy_webgate::prelude::*;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+const MAIN_PORT: WebPort = 8080;
+const DEDICATED_PORT: WebPort = 8081;
+const PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+const CLIENTS: usize = 8;
+const REQUESTS_PER_CLIENT: usize = 8;
+
+fn main() {
+    let payload = Bytes::from(vec![b'x'; PAYLOAD_SIZE]);
+
+    App::new()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / 30.0,
+            ))),
+            BevyWebServerPlugin,
+        ))
+        .port_route(MAIN_PORT, "/payload", {
+            let payload = payload.clone();
+            axum::routing::get(move || async move { payload })
+        })
+        .port_route(DEDICATED_PORT, "/payload", {
+            let payload = payload.clone();
+            axum::routing::get(move || async move { payload })
+        })
+        .port_io_mode(DEDICATED_PORT, IoMode::Dedicated { threads: 4 })
+        .add_systems(Startup, start_benchmark)
+        .run();
+}
+
+fn start_benchmark() {
+    std::thread::spawn(|| {
+        // Give both servers time to bind
+        std::thread::sleep(Duration::from_secs(1));
+
+        for (name, port) in [("main", MAIN_PORT), ("dedicated", DEDICATED_PORT)] {
+            let start = Instant::now();
+            let clients: Vec<_> = (0..CLIENTS)
+                .map(|_| std::thread::spawn(move || run_client(port)))
+                .collect();
+            let bytes: usize = clients
+                .into_iter()
+                .map(|client| client.join().unwrap())
+                .sum();
+            let elapsed = start.elapsed();
+
+            println!(
+                "{:>9} I/O: {} requests in {:.2?}, {:.1} MiB/s",
+                name,
+                CLIENTS * REQUESTS_PER_CLIENT,
+                elapsed,
+                bytes as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64()
+            );
+        }
+
+        std::process::exit(0);
+    });
+}
+
+/// Fetch the payload repeatedly over fresh connections, returning the bytes received
+fn run_client(port: WebPort) -> usize {
+    let mut received = 0;
+    for _ in 0..REQUESTS_PER_CLIENT {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).expect("server is listening");
+        write!(
+            stream,
+            "GET /payload HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+
+        let mut response = Vec::with_capacity(PAYLOAD_SIZE + 1024);
+        stream.read_to_end(&mut response).unwrap();
+        received += response.len();
+    }
+    received
+}
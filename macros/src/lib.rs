@@ -0,0 +1,145 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use syn::{parse_macro_input, LitStr};
+
+/// Embed every file below a directory into the binary as a `bevy_webgate::EmbeddedDir`.
+///
+/// The path is relative to the crate's `Cargo.toml`. Hidden files and directories, whose name
+/// starts with `.`, are skipped. Changed files are picked up on the next build; added or
+/// removed files need the calling crate to be rebuilt.
+///
+/// ```rust,ignore
+/// app.port_static(8080, "/static", embed_dir!("assets/web"));
+/// ```
+#[proc_macro]
+pub fn embed_dir(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr);
+
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let root = Path::new(&manifest_dir).join(dir.value());
+    let mut files = Vec::new();
+    if let Err(err) = collect_files(&root, &root, &mut HashSet::new(), &mut files) {
+        let message = format!("failed to embed {}: {}", root.display(), err);
+        return syn::Error::new(dir.span(), message)
+            .to_compile_error()
+            .into();
+    }
+    files.sort_by(|a, b| a.relative.cmp(&b.relative));
+
+    let source = root.to_string_lossy().into_owned();
+    let files = files.iter().map(|file| {
+        let relative = &file.relative;
+        let absolute = file.absolute.to_string_lossy().into_owned();
+        let etag = &file.etag;
+        let modified = file.modified;
+        quote! {
+            ::bevy_webgate::EmbeddedFile::new(
+                #relative,
+                ::core::include_bytes!(#absolute),
+                #etag,
+                #modified,
+            )
+        }
+    });
+    quote! {{
+        // A constant, since only constant expressions are promoted to `'static`
+        const FILES: &[::bevy_webgate::EmbeddedFile] = &[#(#files),*];
+        ::bevy_webgate::EmbeddedDir::new(#source, FILES)
+    }}
+    .into()
+}
+
+struct FoundFile {
+    /// `/`-separated path below the embedded directory
+    relative: String,
+    absolute: PathBuf,
+    /// Strong ETag built from the contents, so it survives a fresh checkout
+    etag: String,
+    /// Modification time in seconds since the Unix epoch, `0` if unknown
+    modified: u64,
+}
+
+/// Collect the files below `dir`, following symlinks but entering each directory only once,
+/// so a link pointing back up the tree doesn't recurse forever
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<FoundFile>,
+) -> std::io::Result<()> {
+    if !visited.insert(dir.canonicalize()?) {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        // `.gitkeep`, `.DS_Store` and the like aren't meant to be served
+        if entry.file_name().as_encoded_bytes().starts_with(b".") {
+            continue;
+        }
+        let path = entry.path();
+        let metadata = std::fs::metadata(&path)?;
+        if metadata.is_dir() {
+            collect_files(root, &path, visited, files)?;
+            continue;
+        }
+
+        let contents = std::fs::read(&path)?;
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push(FoundFile {
+            relative,
+            absolute: path.canonicalize()?,
+            etag: format!("\"{:x}-{:016x}\"", contents.len(), fnv1a(&contents)),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since_epoch| since_epoch.as_secs()),
+        });
+    }
+    Ok(())
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` gives the same ETags across Rust releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loops_are_entered_once() {
+        let root = std::env::temp_dir().join(format!("webgate-embed-loop-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::write(root.join("css/site.css"), "body {}").unwrap();
+        std::os::unix::fs::symlink(&root, root.join("css/up")).unwrap();
+
+        let mut files = Vec::new();
+        collect_files(&root, &root, &mut HashSet::new(), &mut files).unwrap();
+        let relative: Vec<_> = files.iter().map(|file| file.relative.as_str()).collect();
+        assert_eq!(relative, vec!["css/site.css"]);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,698 @@
+use async_channel::TrySendError;
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap},
+    response::Response,
+};
+use bevy_log::{info, warn};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::task::{Context, Poll};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tower::{Layer, Service};
+
+use crate::RequestContext;
+
+/// Line format used by the access log
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AccessLogFormat {
+    /// NCSA Common Log Format
+    #[default]
+    Common,
+    /// Common Log Format followed by the referer and user agent
+    Combined,
+    /// One JSON object per line, including the request duration and id
+    Json,
+}
+
+/// Where access log lines are written
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum AccessLogTarget {
+    /// Emit lines through `bevy_log` with the `bevy_webgate::access` target
+    #[default]
+    Log,
+    /// Append lines to a file, rotating it once it grows past `max_bytes`.
+    /// Rotated files are named `<path>.1` (newest) to `<path>.<max_files>` (oldest).
+    /// Servers logging to the same file share one writer, rotated with the limits of the
+    /// server that opened it. Lines are dropped, with a warning, if the disk can't keep up.
+    File {
+        path: PathBuf,
+        max_bytes: u64,
+        max_files: usize,
+    },
+}
+
+/// Per-server access log configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessLogConfig {
+    pub format: AccessLogFormat,
+    pub target: AccessLogTarget,
+    /// Fraction of requests to log, from 0.0 (none) to 1.0 (all)
+    pub sample_rate: f64,
+    /// Requests whose path starts with one of these prefixes are never logged
+    pub exclude_paths: Vec<String>,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            format: AccessLogFormat::default(),
+            target: AccessLogTarget::default(),
+            sample_rate: 1.0,
+            exclude_paths: Vec::new(),
+        }
+    }
+}
+
+impl AccessLogConfig {
+    pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_MAX_FILES: usize = 5;
+
+    pub fn new(format: AccessLogFormat) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+
+    /// Write to a rotating file with the default size limits
+    pub fn to_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.target = AccessLogTarget::File {
+            path: path.into(),
+            max_bytes: Self::DEFAULT_MAX_FILE_BYTES,
+            max_files: Self::DEFAULT_MAX_FILES,
+        };
+        self
+    }
+
+    /// Change the rotation limits of a file target
+    pub fn with_rotation(mut self, max_bytes: u64, max_files: usize) -> Self {
+        if let AccessLogTarget::File {
+            max_bytes: target_max_bytes,
+            max_files: target_max_files,
+            ..
+        } = &mut self.target
+        {
+            *target_max_bytes = max_bytes;
+            *target_max_files = max_files;
+        }
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn exclude_path(mut self, prefix: impl Into<String>) -> Self {
+        self.exclude_paths.push(prefix.into());
+        self
+    }
+
+    fn is_excluded(&self, path: &str) -> bool {
+        self.exclude_paths
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+/// Everything known about a request once its response headers are ready
+#[derive(Clone, Debug)]
+pub(crate) struct AccessLogEntry {
+    pub peer: Option<SocketAddr>,
+    pub time: SystemTime,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub status: u16,
+    pub size: Option<u64>,
+    pub duration: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl AccessLogEntry {
+    pub(crate) fn format(&self, format: AccessLogFormat) -> String {
+        let peer = self
+            .peer
+            .map(|peer| peer.ip().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let size = self
+            .size
+            .map(|size| size.to_string())
+            .unwrap_or_else(|| "-".to_string());
+
+        match format {
+            AccessLogFormat::Common => format!(
+                "{peer} - - [{}] \"{} {} {}\" {} {size}",
+                clf_timestamp(self.time),
+                self.method,
+                self.uri,
+                self.version,
+                self.status
+            ),
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                self.format(AccessLogFormat::Common),
+                escape_quoted(self.referer.as_deref().unwrap_or("-")),
+                escape_quoted(self.user_agent.as_deref().unwrap_or("-"))
+            ),
+            AccessLogFormat::Json => serde_json::json!({
+                "peer": self.peer.map(|peer| peer.to_string()),
+                "time": self.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+                "method": self.method,
+                "uri": self.uri,
+                "version": self.version,
+                "status": self.status,
+                "size": self.size,
+                "duration_ms": self.duration.as_micros() as f64 / 1000.0,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "request_id": self.request_id,
+            })
+            .to_string(),
+        }
+    }
+}
+
+fn escape_quoted(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Format a time as `10/Oct/2000:13:55:36 +0000`, the timestamp used by the Common Log Format
+pub(crate) fn clf_timestamp(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Size-limited log file that shifts `<path>.N` files when it rotates
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<BufWriter<File>>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn new(path: PathBuf, max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            path,
+            max_bytes,
+            max_files,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let line_len = line.len() as u64 + 1;
+        if self.max_bytes > 0 && self.size > 0 && self.size + line_len > self.max_bytes {
+            self.rotate()?;
+        }
+
+        if self.file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = file.metadata()?.len();
+            self.file = Some(BufWriter::new(file));
+        }
+
+        if let Some(file) = &mut self.file {
+            // One write per line, so lines are never split between flushes
+            let mut buffer = String::with_capacity(line.len() + 1);
+            buffer.push_str(line);
+            buffer.push('\n');
+            file.write_all(buffer.as_bytes())?;
+            self.size += line_len;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let flushed = self.flush();
+        self.file = None;
+        self.size = 0;
+        flushed?;
+
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        for index in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{index}"));
+    PathBuf::from(rotated)
+}
+
+/// Lines queued for a log file before new ones are dropped, so a slow disk can't make
+/// the queue grow without bound
+const FILE_QUEUE_LINES: usize = 8192;
+
+/// Writers by canonical path, with the thread of each so a replacement can wait for it
+type FileWriters = HashMap<PathBuf, (Weak<FileWriter>, JoinHandle<()>)>;
+
+static FILE_WRITERS: OnceLock<Mutex<FileWriters>> = OnceLock::new();
+
+/// Thread writing one log file, shared by every server logging to it.
+/// The thread exits once the last server using it drops its writer.
+struct FileWriter {
+    lines: async_channel::Sender<String>,
+    /// Lines dropped because the queue was full, reported by the thread
+    dropped: Arc<AtomicU64>,
+}
+
+impl FileWriter {
+    /// Writer of `path`, started with the given rotation limits unless one is running already
+    fn shared(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Arc<Self>> {
+        let key = canonical_log_path(path);
+        let mut writers = FILE_WRITERS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(writer) = writers.get(&key).and_then(|(writer, _)| writer.upgrade()) {
+            return Ok(writer);
+        }
+        // The previous writer of this file has no servers left, let it finish its queue
+        // first so two threads never append to or rotate the same file
+        if let Some((_, thread)) = writers.remove(&key) {
+            let _ = thread.join();
+        }
+
+        let (writer, thread) = Self::spawn(RotatingFile::new(key.clone(), max_bytes, max_files))?;
+        writers.insert(key, (Arc::downgrade(&writer), thread));
+        Ok(writer)
+    }
+
+    fn spawn(mut file: RotatingFile) -> io::Result<(Arc<Self>, JoinHandle<()>)> {
+        let (lines, queued) = async_channel::bounded::<String>(FILE_QUEUE_LINES);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let thread_dropped = dropped.clone();
+        let thread = std::thread::Builder::new()
+            .name("webserver-access-log".to_string())
+            .spawn(move || {
+                let report = |file: &RotatingFile, err: io::Error| {
+                    warn!(
+                        "Failed to write access log to {}: {}",
+                        file.path.display(),
+                        err
+                    );
+                };
+                while let Ok(line) = queued.recv_blocking() {
+                    if let Err(err) = file.write_line(&line) {
+                        report(&file, err);
+                    }
+                    // Flush once the queue is drained, so a burst of requests shares one write
+                    if queued.is_empty() {
+                        if let Err(err) = file.flush() {
+                            report(&file, err);
+                        }
+                        let dropped = thread_dropped.swap(0, Ordering::Relaxed);
+                        if dropped > 0 {
+                            warn!(
+                                "Dropped {} access log lines for {}, the disk can't keep up",
+                                dropped,
+                                file.path.display()
+                            );
+                        }
+                    }
+                }
+            })?;
+        Ok((Arc::new(Self { lines, dropped }), thread))
+    }
+
+    fn write(&self, line: String) {
+        match self.lines.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // Only if the writer thread panicked
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+/// Key identifying a log file however its path is spelled, also before it exists
+fn canonical_log_path(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|parent| parent.join(name))
+            .unwrap_or(absolute),
+        _ => absolute,
+    }
+}
+
+enum AccessLogWriter {
+    Log,
+    /// Lines queued for the thread writing the file, so disk I/O never runs in a request
+    File(Arc<FileWriter>),
+}
+
+impl AccessLogWriter {
+    fn write(&self, line: String) {
+        match self {
+            Self::Log => info!(target: "bevy_webgate::access", "{}", line),
+            Self::File(writer) => writer.write(line),
+        }
+    }
+}
+
+struct AccessLogState {
+    config: AccessLogConfig,
+    writer: AccessLogWriter,
+    seen: AtomicU64,
+}
+
+impl AccessLogState {
+    /// Spread the sampled requests evenly instead of relying on randomness
+    fn sampled(&self) -> bool {
+        let rate = self.config.sample_rate;
+        if rate >= 1.0 {
+            return true;
+        }
+        if rate <= 0.0 {
+            return false;
+        }
+
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((seen + 1.0) * rate).floor() > (seen * rate).floor()
+    }
+}
+
+/// Tower layer writing one access log line per request
+#[derive(Clone)]
+pub(crate) struct AccessLogLayer {
+    state: Arc<AccessLogState>,
+}
+
+impl AccessLogLayer {
+    pub(crate) fn new(config: AccessLogConfig) -> Self {
+        let writer = match &config.target {
+            AccessLogTarget::Log => AccessLogWriter::Log,
+            AccessLogTarget::File {
+                path,
+                max_bytes,
+                max_files,
+            } => FileWriter::shared(path, *max_bytes, *max_files)
+                .map(AccessLogWriter::File)
+                .unwrap_or_else(|err| {
+                    warn!(
+                        "Failed to start the access log writer for {}, logging instead: {}",
+                        path.display(),
+                        err
+                    );
+                    AccessLogWriter::Log
+                }),
+        };
+
+        Self {
+            state: Arc::new(AccessLogState {
+                config,
+                writer,
+                seen: AtomicU64::new(0),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AccessLogService<S> {
+    inner: S,
+    state: Arc<AccessLogState>,
+}
+
+impl<S> Service<Request> for AccessLogService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        if self.state.config.is_excluded(request.uri().path()) || !self.state.sampled() {
+            return Box::pin(self.inner.call(request));
+        }
+
+        let start_time = Instant::now();
+        let mut entry = AccessLogEntry {
+            peer: request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| *peer),
+            time: SystemTime::now(),
+            method: request.method().to_string(),
+            uri: request
+                .uri()
+                .path_and_query()
+                .map(|path| path.to_string())
+                .unwrap_or_else(|| request.uri().path().to_string()),
+            version: format!("{:?}", request.version()),
+            status: 0,
+            size: None,
+            duration: Duration::ZERO,
+            referer: header_string(request.headers(), header::REFERER),
+            user_agent: header_string(request.headers(), header::USER_AGENT),
+            request_id: request
+                .extensions()
+                .get::<RequestContext>()
+                .map(|context| context.id().to_string()),
+        };
+
+        let state = self.state.clone();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+
+            entry.status = response.status().as_u16();
+            entry.duration = start_time.elapsed();
+            entry.size = response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .or_else(|| response.body().size_hint().exact());
+
+            state.writer.write(entry.format(state.config.format));
+
+            Ok(response)
+        })
+    }
+}
+
+fn header_string(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            peer: Some("127.0.0.1:54321".parse().unwrap()),
+            time: UNIX_EPOCH + Duration::from_secs(971_186_136),
+            method: "GET".to_string(),
+            uri: "/apache_pb.gif?x=1".to_string(),
+            version: "HTTP/1.0".to_string(),
+            status: 200,
+            size: Some(2326),
+            duration: Duration::from_millis(12),
+            referer: Some("http://www.example.com/start.html".to_string()),
+            user_agent: Some("Mozilla/4.08 \"test\"".to_string()),
+            request_id: Some("abc123".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_clf_timestamp() {
+        assert_eq!(clf_timestamp(UNIX_EPOCH), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(
+            clf_timestamp(UNIX_EPOCH + Duration::from_secs(971_186_136)),
+            "10/Oct/2000:13:55:36 +0000"
+        );
+        // Leap day
+        assert_eq!(
+            clf_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_164_800)),
+            "29/Feb/2024:00:00:00 +0000"
+        );
+    }
+
+    #[test]
+    fn test_common_and_combined_format() {
+        let entry = entry();
+        assert_eq!(
+            entry.format(AccessLogFormat::Common),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif?x=1 HTTP/1.0\" 200 2326"
+        );
+        assert_eq!(
+            entry.format(AccessLogFormat::Combined),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif?x=1 HTTP/1.0\" 200 2326 \
+             \"http://www.example.com/start.html\" \"Mozilla/4.08 \\\"test\\\"\""
+        );
+    }
+
+    #[test]
+    fn test_json_format() {
+        let line = entry().format(AccessLogFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["method"], "GET");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["size"], 2326);
+        assert_eq!(value["peer"], "127.0.0.1:54321");
+        assert_eq!(value["duration_ms"], 12.0);
+        assert_eq!(value["request_id"], "abc123");
+    }
+
+    #[test]
+    fn test_sampling_and_exclusion() {
+        let config = AccessLogConfig::default()
+            .with_sample_rate(0.25)
+            .exclude_path("/health");
+        assert!(config.is_excluded("/health"));
+        assert!(config.is_excluded("/healthz"));
+        assert!(!config.is_excluded("/api"));
+
+        let layer = AccessLogLayer::new(config);
+        let sampled = (0..100).filter(|_| layer.state.sampled()).count();
+        assert_eq!(sampled, 25);
+    }
+
+    #[test]
+    fn test_file_rotation() {
+        let dir = std::env::temp_dir().join(format!("bevy_webgate_access_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("access.log");
+
+        let mut file = RotatingFile::new(path.clone(), 10, 2);
+        for line in ["aaaaaaaa", "bbbbbbbb", "cccccccc", "dddddddd"] {
+            file.write_line(line).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddddddd\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "cccccccc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "bbbbbbbb\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_file_writer_thread() {
+        let dir =
+            std::env::temp_dir().join(format!("bevy_webgate_access_thread_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("access.log");
+
+        let writer = FileWriter::shared(&path, 0, 1).unwrap();
+        // Another server logging to the same file, spelled differently, shares the thread
+        let same = FileWriter::shared(&dir.join(".").join("access.log"), 0, 1).unwrap();
+        assert!(Arc::ptr_eq(&writer, &same));
+        writer.write("first".to_string());
+        same.write("second".to_string());
+        drop(writer);
+        drop(same);
+
+        // Written and flushed by the thread once it drains the queue
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fs::read_to_string(&path).unwrap_or_default() != "first\nsecond\n" {
+            assert!(Instant::now() < deadline, "access log was not written");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,15 +1,30 @@
+use axum::extract::Request;
 use axum::handler::Handler;
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
-use axum::routing::{MethodRouter, Route};
+use axum::routing::{any, get, MethodFilter, MethodRouter, Route};
 use axum::Router;
-use bevy_app::App;
+use bevy_app::{App, Last, Update};
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Event;
+use bevy_ecs::query::QueryFilter;
+use bevy_ecs::schedule::ScheduleLabel;
+use bevy_ecs::system::{In, IntoSystem};
 use bevy_ecs::world::Mut;
+use serde::Serialize;
 use std::convert::Infallible;
 use std::net::IpAddr;
 use tower::{Layer, Service};
 
+use crate::component_stream::{component_stream, publish_component_changes, ComponentStreams};
+use crate::deterministic::deterministic_layer;
+use crate::sse::{broadcast_sse_events, last_event_id, sse_response, SseChannel, SseStreams};
+use crate::system_handler::{system_method_router, WebRequest};
+#[cfg(feature = "compression")]
+use crate::CompressionConfig;
 use crate::{
-    BevyWebServerPlugin, WebPort, WebServer, WebServerConfig, WebServerManager, WebServerResult,
+    AccessLogConfig, BevyWebServerPlugin, HttpErrorResponses, IoMode, SseConfig, StaticDir,
+    WebPort, WebServer, WebServerConfig, WebServerManager, WebServerResult, WebSocketConfig,
     DEFAULT_IP, DEFAULT_PORT,
 };
 
@@ -94,6 +109,125 @@ pub trait WebServerAppExt {
         H: Handler<T, ()>,
         T: 'static;
 
+    /// Serve a specific port's error pages from `responses` instead of the app's
+    /// [`HttpErrorResponses`] resource
+    fn port_error_responses(&mut self, port: WebPort, responses: HttpErrorResponses) -> &mut Self;
+
+    /// Handle requests to known paths with an unsupported method on a specific port,
+    /// replacing the default 405 page
+    fn port_method_not_allowed_fallback<H, T>(&mut self, port: WebPort, handler: H) -> &mut Self
+    where
+        H: Handler<T, ()>,
+        T: 'static;
+
+    /// Serve the files of `dir` below `mount` on a specific port, e.g.
+    /// `app.port_static(8080, "/static", "assets/web")`.
+    ///
+    /// A relative `dir` is resolved against the working directory, see [`StaticDir`].
+    fn port_static(&mut self, port: WebPort, mount: &str, dir: impl Into<StaticDir>) -> &mut Self;
+
+    /// Expose server statistics and Bevy diagnostics in OpenMetrics format on a specific port
+    fn port_metrics(&mut self, port: WebPort, path: &str) -> &mut Self;
+
+    /// Write an access log line for every request served on a specific port
+    fn port_access_log(&mut self, port: WebPort, config: AccessLogConfig) -> &mut Self;
+
+    /// Compress responses on a specific port for clients that accept it, see [`CompressionConfig`]
+    #[cfg(feature = "compression")]
+    fn port_compression(&mut self, port: WebPort, config: CompressionConfig) -> &mut Self;
+
+    /// Choose where a specific port drives its network I/O, see [`IoMode`]
+    fn port_io_mode(&mut self, port: WebPort, io_mode: IoMode) -> &mut Self;
+
+    /// Accept WebSocket connections on a specific port and path.
+    ///
+    /// Each connection is spawned as an entity with a [`WebSocketConnection`](crate::WebSocketConnection)
+    /// component; traffic is exchanged through the WebSocket events.
+    fn port_websocket(&mut self, port: WebPort, path: &str) -> &mut Self {
+        self.port_websocket_with_config(port, path, WebSocketConfig::default())
+    }
+
+    /// Like [`port_websocket`](Self::port_websocket) with custom buffering limits
+    fn port_websocket_with_config(
+        &mut self,
+        port: WebPort,
+        path: &str,
+        config: WebSocketConfig,
+    ) -> &mut Self;
+
+    /// Stream every `E` event as JSON over Server-Sent Events on a specific port and path.
+    ///
+    /// Reconnecting clients get the events they missed, or a `reset` event if some of them
+    /// have already left the history.
+    fn port_sse<E: Event + Serialize>(&mut self, port: WebPort, path: &str) -> &mut Self {
+        self.port_sse_with_config::<E>(port, path, SseConfig::default())
+    }
+
+    /// Like [`port_sse`](Self::port_sse) with custom buffering limits
+    fn port_sse_with_config<E: Event + Serialize>(
+        &mut self,
+        port: WebPort,
+        path: &str,
+        config: SseConfig,
+    ) -> &mut Self;
+
+    /// Stream additions, changes and removals of component `C` as JSON over Server-Sent Events.
+    ///
+    /// Clients receive an `added` message for every current component first, then
+    /// `added`, `changed` and `removed` messages each frame. `?entity=<bits>` restricts
+    /// the stream to a single entity.
+    fn port_component_stream<C: Component + Serialize>(
+        &mut self,
+        port: WebPort,
+        path: &str,
+    ) -> &mut Self {
+        self.port_component_stream_filtered::<C, ()>(port, path)
+    }
+
+    /// Like [`port_component_stream`](Self::port_component_stream), limited to entities matching `F`,
+    /// e.g. `With<Player>`
+    fn port_component_stream_filtered<C: Component + Serialize, F: QueryFilter + 'static>(
+        &mut self,
+        port: WebPort,
+        path: &str,
+    ) -> &mut Self;
+
+    /// Process mutating requests on a specific port in `schedule`, e.g. `FixedPreUpdate`.
+    ///
+    /// `POST`, `PUT`, `PATCH` and `DELETE` requests are held until the next run of `schedule`,
+    /// then their handlers run there one by one in arrival order, so `AsyncWorld` access up
+    /// to a handler's first await happens at a well-defined point of the frame. Safe methods
+    /// are not held. Handlers that await across frames still resume outside the schedule;
+    /// use [`port_route_system_in`](Self::port_route_system_in) for those mutations.
+    ///
+    /// Held requests count as in flight in [`ServerStats`](crate::ServerStats), and their
+    /// latency includes the time spent waiting for `schedule`.
+    fn port_deterministic(&mut self, port: WebPort, schedule: impl ScheduleLabel) -> &mut Self;
+
+    /// Handle requests to `path` on a specific port with a Bevy system run in `Update`.
+    ///
+    /// The system receives the request as `In<WebRequest>` and may use any system parameter;
+    /// its output is sent back as the response.
+    fn port_route_system<R: IntoResponse + 'static, M>(
+        &mut self,
+        port: WebPort,
+        path: &str,
+        method: MethodFilter,
+        system: impl IntoSystem<In<WebRequest>, R, M> + 'static,
+    ) -> &mut Self {
+        self.port_route_system_in(port, Update, path, method, system)
+    }
+
+    /// Like [`port_route_system`](Self::port_route_system), running the system in `schedule`
+    fn port_route_system_in<R: IntoResponse + 'static, M>(
+        &mut self,
+        port: WebPort,
+        schedule: impl ScheduleLabel,
+        path: &str,
+        method: MethodFilter,
+        system: impl IntoSystem<In<WebRequest>, R, M> + 'static,
+    ) -> &mut Self;
+
     /// Get information about running servers
     fn running_servers(&self) -> Vec<(WebPort, IpAddr)>;
 
@@ -113,7 +247,7 @@ impl WebServerAppExt for App {
 
         self.world_mut()
             .resource_scope(|_world, mut manager: Mut<WebServerManager>| {
-                let _ = manager.add_server(WebServer::new(ip, port, Router::new()));
+                let _ = manager.add_server(WebServer::new(ip, port, WebServer::default_router()));
             });
         self
     }
@@ -174,7 +308,7 @@ impl WebServerAppExt for App {
                 let existing_router = manager
                     .get_server(&port)
                     .map(|srv| srv.router().clone())
-                    .unwrap_or_else(|| Router::new());
+                    .unwrap_or_else(WebServer::default_router);
 
                 let new_router = router_fn(existing_router);
                 if !manager.has_server(&port) {
@@ -225,6 +359,153 @@ impl WebServerAppExt for App {
         self.port_router(port, |r| r.fallback(handler))
     }
 
+    fn port_error_responses(&mut self, port: WebPort, responses: HttpErrorResponses) -> &mut Self {
+        // Make sure the server exists before configuring it
+        self.port_router(port, |router| router);
+        self.world_mut()
+            .resource_mut::<WebServerManager>()
+            .set_error_responses(&port, Some(responses));
+        self
+    }
+
+    fn port_method_not_allowed_fallback<H, T>(&mut self, port: WebPort, handler: H) -> &mut Self
+    where
+        H: Handler<T, ()>,
+        T: 'static,
+    {
+        // Make sure the server exists before configuring it
+        self.port_router(port, |router| router);
+        self.world_mut()
+            .resource_mut::<WebServerManager>()
+            .set_method_not_allowed_fallback(&port, any(handler));
+        self
+    }
+
+    fn port_static(&mut self, port: WebPort, mount: &str, dir: impl Into<StaticDir>) -> &mut Self {
+        let static_router = dir.into().router(mount);
+        self.port_router(port, |router| router.merge(static_router))
+    }
+
+    fn port_metrics(&mut self, port: WebPort, path: &str) -> &mut Self {
+        self.port_route(port, path, get(crate::openmetrics_handler))
+    }
+
+    fn port_access_log(&mut self, port: WebPort, config: AccessLogConfig) -> &mut Self {
+        // Make sure the server exists before configuring it
+        self.port_router(port, |router| router);
+        self.world_mut()
+            .resource_mut::<WebServerManager>()
+            .set_access_log(&port, Some(config));
+        self
+    }
+
+    #[cfg(feature = "compression")]
+    fn port_compression(&mut self, port: WebPort, config: CompressionConfig) -> &mut Self {
+        // Make sure the server exists before configuring it
+        self.port_router(port, |router| router);
+        self.world_mut()
+            .resource_mut::<WebServerManager>()
+            .set_compression(&port, Some(config));
+        self
+    }
+
+    fn port_io_mode(&mut self, port: WebPort, io_mode: IoMode) -> &mut Self {
+        // Make sure the server exists before configuring it
+        self.port_router(port, |router| router);
+        self.world_mut()
+            .resource_mut::<WebServerManager>()
+            .set_io_mode(&port, io_mode);
+        self
+    }
+
+    fn port_websocket_with_config(
+        &mut self,
+        port: WebPort,
+        path: &str,
+        config: WebSocketConfig,
+    ) -> &mut Self {
+        let route_path = path.to_string();
+        self.port_route(
+            port,
+            path,
+            get(move |request: Request| {
+                crate::websocket::websocket_upgrade(port, route_path.clone(), config, request)
+            }),
+        )
+    }
+
+    fn port_sse_with_config<E: Event + Serialize>(
+        &mut self,
+        port: WebPort,
+        path: &str,
+        config: SseConfig,
+    ) -> &mut Self {
+        if !self.world().contains_resource::<SseStreams<E>>() {
+            self.add_event::<E>()
+                .init_resource::<SseStreams<E>>()
+                .add_systems(Last, broadcast_sse_events::<E>);
+        }
+
+        let channel = SseChannel::new(config);
+        self.world_mut()
+            .resource_mut::<SseStreams<E>>()
+            .push(channel.clone());
+
+        self.port_route(
+            port,
+            path,
+            get(move |headers: HeaderMap| {
+                let receiver = channel.subscribe_events(last_event_id(&headers));
+                let keep_alive = channel.keep_alive();
+                async move { sse_response(receiver, keep_alive) }
+            }),
+        )
+    }
+
+    fn port_component_stream_filtered<C: Component + Serialize, F: QueryFilter + 'static>(
+        &mut self,
+        port: WebPort,
+        path: &str,
+    ) -> &mut Self {
+        if !self.world().contains_resource::<ComponentStreams<C, F>>() {
+            self.init_resource::<ComponentStreams<C, F>>()
+                .add_systems(Last, publish_component_changes::<C, F>);
+        }
+
+        let channel = SseChannel::new(SseConfig::default());
+        self.world_mut()
+            .resource_mut::<ComponentStreams<C, F>>()
+            .push(channel.clone());
+
+        self.port_route(
+            port,
+            path,
+            get(move |request: Request| component_stream::<C, F>(channel.clone(), request)),
+        )
+    }
+
+    fn port_deterministic(&mut self, port: WebPort, schedule: impl ScheduleLabel) -> &mut Self {
+        // Make sure the server exists before configuring it
+        self.port_router(port, |router| router);
+        let layer = deterministic_layer(self, schedule);
+        self.world_mut()
+            .resource_mut::<WebServerManager>()
+            .set_deterministic(&port, Some(layer));
+        self
+    }
+
+    fn port_route_system_in<R: IntoResponse + 'static, M>(
+        &mut self,
+        port: WebPort,
+        schedule: impl ScheduleLabel,
+        path: &str,
+        method: MethodFilter,
+        system: impl IntoSystem<In<WebRequest>, R, M> + 'static,
+    ) -> &mut Self {
+        let method_router = system_method_router(self, schedule, method, system);
+        self.port_route(port, path, method_router)
+    }
+
     fn running_servers(&self) -> Vec<(WebPort, IpAddr)> {
         let running_servers = self.world().get_resource::<WebServerManager>();
         let manager = self.world().get_resource::<WebServerManager>();
@@ -278,6 +559,14 @@ impl WebServerAppExt for App {
 pub trait RouterAppExt {
     fn router(&mut self, router_fn: impl FnOnce(Router) -> Router);
     fn route(&mut self, path: &str, method_router: MethodRouter<()>) -> &mut Self;
+    /// Handle requests to `path` with a Bevy system run in `Update`,
+    /// see [`WebServerAppExt::port_route_system`]
+    fn route_system<R: IntoResponse + 'static, M>(
+        &mut self,
+        path: &str,
+        method: MethodFilter,
+        system: impl IntoSystem<In<WebRequest>, R, M> + 'static,
+    ) -> &mut Self;
     fn route_service<T>(&mut self, path: &str, service: T) -> &mut Self
     where
         T: Service<axum::extract::Request, Error = Infallible> + Clone + Send + Sync + 'static,
@@ -336,14 +625,17 @@ impl RouterAppExt for App {
                         (config.ip, config.port)
                     });
                 if !manager.has_server(&default_port) {
-                    let _ =
-                        manager.add_server(WebServer::new(default_ip, default_port, Router::new()));
+                    let _ = manager.add_server(WebServer::new(
+                        default_ip,
+                        default_port,
+                        WebServer::default_router(),
+                    ));
                 }
 
                 let existing_router = manager
                     .get_server(&default_port)
                     .map(|srv| srv.router().clone())
-                    .unwrap_or_else(|| Router::new());
+                    .unwrap_or_else(WebServer::default_router);
 
                 let new_router = router_fn(existing_router);
                 manager.set_router(&default_port, new_router);
@@ -355,6 +647,16 @@ impl RouterAppExt for App {
         self
     }
 
+    fn route_system<R: IntoResponse + 'static, M>(
+        &mut self,
+        path: &str,
+        method: MethodFilter,
+        system: impl IntoSystem<In<WebRequest>, R, M> + 'static,
+    ) -> &mut Self {
+        let method_router = system_method_router(self, Update, method, system);
+        self.route(path, method_router)
+    }
+
     fn route_service<T>(&mut self, path: &str, service: T) -> &mut Self
     where
         T: Service<axum::extract::Request, Error = Infallible> + Clone + Send + Sync + 'static,
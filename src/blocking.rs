@@ -0,0 +1,162 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use bevy_defer::AsyncWorld;
+use bevy_ecs::world::World;
+use bevy_tasks::{AsyncComputeTaskPool, ComputeTaskPool, TaskPool};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use crate::{HttpErrorResponses, WebRequest};
+
+/// Run `work` on `pool`, resolving to `None` if it panicked
+pub(crate) async fn run_on_pool<T: Send + 'static>(
+    pool: &TaskPool,
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let (sender, receiver) = async_channel::bounded(1);
+    // Single-threaded pools want `Sync` futures, the mutex lets any `Send` work through
+    let work = Mutex::new(work);
+    pool.spawn(async move {
+        if let Ok(work) = work.into_inner() {
+            let _ = sender.try_send(work());
+        }
+    })
+    .detach();
+    receiver.recv().await.ok()
+}
+
+/// Run CPU-heavy `work` on the [`ComputeTaskPool`] and wait for its result.
+///
+/// The compute pool also runs the app's systems, so keep this to work that finishes quickly;
+/// use [`spawn_async_compute`] for anything that may block.
+///
+/// # Panics
+///
+/// Panics if `work` panics.
+pub async fn spawn_compute<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    run_on_pool(ComputeTaskPool::get_or_init(TaskPool::default), work)
+        .await
+        .expect("compute work panicked")
+}
+
+/// Run blocking or long-running `work` on the [`AsyncComputeTaskPool`] and wait for its result,
+/// keeping the executor that serves connections free meanwhile.
+///
+/// ```rust,ignore
+/// async fn download() -> Vec<u8> {
+///     spawn_async_compute(|| std::fs::read("exports/world.bin").unwrap_or_default()).await
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if `work` panics.
+pub async fn spawn_async_compute<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> T {
+    run_on_pool(AsyncComputeTaskPool::get_or_init(TaskPool::default), work)
+        .await
+        .expect("async compute work panicked")
+}
+
+/// Copy what `work` needs out of the world, then run it on the [`AsyncComputeTaskPool`].
+///
+/// ```rust,ignore
+/// async fn report() -> Html<String> {
+///     compute_with_world(
+///         |world| world.query::<&Score>().iter(world).map(|score| score.0).collect::<Vec<_>>(),
+///         |scores| Html(render_report(&scores)),
+///     )
+///     .await
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if `work` panics.
+pub async fn compute_with_world<S, T>(
+    read: impl FnOnce(&mut World) -> S,
+    work: impl FnOnce(S) -> T + Send + 'static,
+) -> T
+where
+    S: Send + 'static,
+    T: Send + 'static,
+{
+    let snapshot = AsyncWorld.run(read);
+    spawn_async_compute(move || work(snapshot)).await
+}
+
+/// Handler running the blocking function `f` on the [`AsyncComputeTaskPool`].
+///
+/// When `f` panics on one of the pool's threads the request is answered with
+/// `500 Internal Server Error`.
+///
+/// ```rust,ignore
+/// fn export(request: WebRequest) -> Vec<u8> {
+///     std::fs::read(format!("exports/{}", request.param("name").unwrap_or_default()))
+///         .unwrap_or_default()
+/// }
+///
+/// app.route("/exports/{name}", get(blocking(export)));
+/// ```
+pub fn blocking<F, R>(
+    f: F,
+) -> impl Fn(WebRequest) -> Pin<Box<dyn Future<Output = Response> + Send>> + Clone + Send + Sync + 'static
+where
+    F: Fn(WebRequest) -> R + Clone + Send + Sync + 'static,
+    R: IntoResponse + 'static,
+{
+    move |request| {
+        let f = f.clone();
+        Box::pin(async move {
+            let pool = AsyncComputeTaskPool::get_or_init(TaskPool::default);
+            match run_on_pool(pool, move || f(request).into_response()).await {
+                Some(response) => response,
+                None => HttpErrorResponses::respond(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_app::{App, TaskPoolPlugin};
+    use std::task::{Context, Poll, Waker};
+
+    /// Poll `future` between app updates, which tick the pools of single-threaded builds
+    fn run_with_app<T>(future: impl Future<Output = T>) -> T {
+        let mut app = App::new();
+        app.add_plugins(TaskPoolPlugin::default());
+        let mut future = Box::pin(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        for _ in 0..1000 {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("work did not finish");
+    }
+
+    #[test]
+    fn test_async_compute_returns_result() {
+        assert_eq!(run_with_app(spawn_async_compute(|| 6 * 7)), 42);
+    }
+
+    #[test]
+    fn test_blocking_handler_is_routable() {
+        let _router: axum::Router = axum::Router::new().route(
+            "/report",
+            axum::routing::get(blocking(|request: WebRequest| request.uri.to_string())),
+        );
+    }
+
+    #[test]
+    fn test_compute_with_non_sync_work() {
+        let cell = std::cell::Cell::new(2);
+        assert_eq!(run_with_app(spawn_compute(move || cell.get() * 21)), 42);
+    }
+}
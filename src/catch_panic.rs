@@ -0,0 +1,149 @@
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode, Uri},
+    response::Response,
+};
+use bevy_log::error;
+use futures_util::FutureExt;
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use crate::{HttpErrorResponses, ServerStats};
+
+/// Message carried by a panic payload, if it is a string
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>")
+}
+
+/// Log a handler panic and answer it with the 500 page
+fn recover(
+    stats: &ServerStats,
+    method: &Method,
+    uri: &Uri,
+    payload: Box<dyn Any + Send>,
+) -> Response {
+    stats.record_panic();
+    // Logged inside the request span, so the request ID is attached
+    error!(
+        "Handler for {} {} panicked: {}",
+        method,
+        uri,
+        panic_message(payload.as_ref())
+    );
+    HttpErrorResponses::respond(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Tower layer turning handler panics into `500 Internal Server Error` responses
+#[derive(Clone)]
+pub(crate) struct CatchPanicLayer {
+    stats: ServerStats,
+}
+
+impl CatchPanicLayer {
+    pub(crate) fn new(stats: ServerStats) -> Self {
+        Self { stats }
+    }
+}
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanicService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanicService {
+            inner,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CatchPanicService<S> {
+    inner: S,
+    stats: ServerStats,
+}
+
+impl<S> Service<Request> for CatchPanicService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        let stats = self.stats.clone();
+
+        // Handlers may panic while the future is created as well as while it is polled
+        let future = match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(request))) {
+            Ok(future) => future,
+            Err(payload) => {
+                let response = recover(&stats, &method, &uri, payload);
+                return Box::pin(async move { Ok(response) });
+            }
+        };
+
+        Box::pin(async move {
+            match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(result) => result,
+                Err(payload) => Ok(recover(&stats, &method, &uri, payload)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn panicking_handler() -> &'static str {
+        panic!("handler failed")
+    }
+
+    #[test]
+    fn test_panicking_handler_answers_500() {
+        let stats = ServerStats::default();
+        let router = Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route("/panic", get(panicking_handler))
+            .layer(CatchPanicLayer::new(stats.clone()));
+
+        let call = |path: &str| {
+            let request = Request::get(path).body(Body::empty()).unwrap();
+            futures_util::FutureExt::now_or_never(router.clone().oneshot(request))
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(call("/panic").status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(call("/ok").status(), StatusCode::OK);
+        assert_eq!(stats.handler_panics(), 1);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload: Box<dyn Any + Send> = Box::new(String::from("boom"));
+        assert_eq!(panic_message(payload.as_ref()), "boom");
+        let payload: Box<dyn Any + Send> = Box::new(7);
+        assert_eq!(
+            panic_message(payload.as_ref()),
+            "<non-string panic payload>"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use bevy_defer::AsyncWorld;
+use bevy_ecs::{entity::EntityHashSet, prelude::*, query::QueryFilter};
+use bevy_log::warn;
+use serde::Serialize;
+use serde_json::json;
+use std::marker::PhantomData;
+
+use crate::sse::{last_event_id, sse_response, SseChannel, SseMessage};
+use crate::HttpErrorResponses;
+
+const ADDED_EVENT: &str = "added";
+const CHANGED_EVENT: &str = "changed";
+const REMOVED_EVENT: &str = "removed";
+
+/// Change streams of component `C` on entities matching `F`
+#[derive(Resource)]
+pub(crate) struct ComponentStreams<C, F> {
+    channels: Vec<SseChannel>,
+    /// Entities an `added` message was published for, so removals can be matched against `F`
+    published: EntityHashSet,
+    marker: PhantomData<fn(C, F)>,
+}
+
+impl<C, F> Default for ComponentStreams<C, F> {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            published: EntityHashSet::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C, F> ComponentStreams<C, F> {
+    pub(crate) fn push(&mut self, channel: SseChannel) {
+        self.channels.push(channel);
+    }
+
+    fn publish(&self, message: SseMessage) {
+        for channel in &self.channels {
+            channel.publish(message.clone());
+        }
+    }
+}
+
+fn component_message<C: Serialize>(
+    event: &'static str,
+    entity: Entity,
+    value: Option<&C>,
+) -> Option<SseMessage> {
+    let mut data = json!({ "entity": entity.to_bits() });
+    if let Some(value) = value {
+        match serde_json::to_value(value) {
+            Ok(value) => data["value"] = value,
+            Err(err) => {
+                warn!(
+                    "Failed to serialize {} for a component stream: {}",
+                    std::any::type_name::<C>(),
+                    err
+                );
+                return None;
+            }
+        }
+    }
+    Some(SseMessage::new(Some(event), Some(entity), data.to_string()))
+}
+
+/// Publish this frame's additions, changes and removals of `C`, where entities that
+/// stopped matching `F` count as removed and entities that match it again as added
+pub(crate) fn publish_component_changes<C, F>(
+    matching: Query<(Entity, Ref<C>), F>,
+    mut removed: RemovedComponents<C>,
+    mut streams: ResMut<ComponentStreams<C, F>>,
+) where
+    C: Component + Serialize,
+    F: QueryFilter + 'static,
+{
+    let removed: Vec<Entity> = removed
+        .read()
+        .chain(
+            streams
+                .published
+                .iter()
+                .copied()
+                .filter(|entity| !matching.contains(*entity)),
+        )
+        .collect();
+    for entity in removed {
+        if streams.published.remove(&entity) {
+            if let Some(message) = component_message::<C>(REMOVED_EVENT, entity, None) {
+                streams.publish(message);
+            }
+        }
+    }
+
+    for (entity, value) in &matching {
+        // Entities not published yet are new or (re-)entered `F` without `C` changing
+        let event = if value.is_added() || !streams.published.contains(&entity) {
+            ADDED_EVENT
+        } else if value.is_changed() {
+            CHANGED_EVENT
+        } else {
+            continue;
+        };
+        streams.published.insert(entity);
+        if let Some(message) = component_message(event, entity, Some(&*value)) {
+            streams.publish(message);
+        }
+    }
+}
+
+/// Entity filter given as `?entity=<bits>`, `Err` if present but not a valid entity
+fn entity_filter(query: Option<&str>) -> Result<Option<Entity>, ()> {
+    let Some(value) = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == "entity").then_some(value)
+        })
+    else {
+        return Ok(None);
+    };
+
+    let bits = value.parse().map_err(|_| ())?;
+    Entity::try_from_bits(bits).map(Some).map_err(|_| ())
+}
+
+/// Subscribe a client, starting with an `added` message for every current match unless
+/// it is resuming and the history still holds everything it missed
+pub(crate) async fn component_stream<C, F>(channel: SseChannel, request: Request) -> Response
+where
+    C: Component + Serialize,
+    F: QueryFilter + 'static,
+{
+    let Ok(entity) = entity_filter(request.uri().query()) else {
+        return HttpErrorResponses::respond(StatusCode::BAD_REQUEST);
+    };
+    let resume_from = last_event_id(request.headers());
+
+    let receiver = AsyncWorld.run(|world| {
+        // A resuming client already saw the snapshot, it only needs the missed changes.
+        // One whose changes have rolled out of the history starts over instead.
+        let resume_from = resume_from.filter(|last_event_id| channel.can_resume(*last_event_id));
+        let initial = if resume_from.is_some() {
+            Vec::new()
+        } else {
+            let mut query = world.query_filtered::<(Entity, &C), F>();
+            query
+                .iter(world)
+                .filter(|(current, _)| entity.is_none_or(|entity| entity == *current))
+                .filter_map(|(current, value)| component_message(ADDED_EVENT, current, Some(value)))
+                .collect()
+        };
+        // Subscribing with exclusive world access means no publish can slip in between
+        channel.subscribe(resume_from, entity, initial)
+    });
+
+    sse_response(receiver, channel.keep_alive()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entity_filter() {
+        let entity = Entity::from_raw(7);
+        let query = format!("format=json&entity={}", entity.to_bits());
+
+        assert_eq!(entity_filter(None), Ok(None));
+        assert_eq!(entity_filter(Some("format=json")), Ok(None));
+        assert_eq!(entity_filter(Some(&query)), Ok(Some(entity)));
+        assert!(entity_filter(Some("entity=abc")).is_err());
+    }
+
+    #[derive(Component)]
+    struct Health(u32);
+
+    impl Serialize for Health {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize(serializer)
+        }
+    }
+
+    #[derive(Component)]
+    struct Tracked;
+
+    #[test]
+    fn test_leaving_and_reentering_the_filter() {
+        let mut world = World::new();
+        let channel = SseChannel::new(Default::default());
+        let receiver = channel.subscribe(None, None, Vec::new());
+        let mut streams = ComponentStreams::<Health, With<Tracked>>::default();
+        streams.push(channel);
+        world.insert_resource(streams);
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(publish_component_changes::<Health, With<Tracked>>);
+
+        let entity = world.spawn((Health(10), Tracked)).id();
+        schedule.run(&mut world);
+        world.entity_mut(entity).remove::<Tracked>();
+        schedule.run(&mut world);
+        // Not matching anymore, so neither changes nor the removal are published again
+        world.get_mut::<Health>(entity).unwrap().0 = 5;
+        schedule.run(&mut world);
+        // Matching again with an unchanged `Health` is published as added
+        world.entity_mut(entity).insert(Tracked);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        world.entity_mut(entity).remove::<Health>();
+        schedule.run(&mut world);
+
+        let events: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|message| (message.event.unwrap(), message.data.to_string()))
+            .collect();
+        let bits = entity.to_bits();
+        assert_eq!(
+            events,
+            vec![
+                ("added", format!(r#"{{"entity":{bits},"value":10}}"#)),
+                ("removed", format!(r#"{{"entity":{bits}}}"#)),
+                ("added", format!(r#"{{"entity":{bits},"value":5}}"#)),
+                ("removed", format!(r#"{{"entity":{bits}}}"#)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_publish_component_changes() {
+        let mut world = World::new();
+        let channel = SseChannel::new(Default::default());
+        let receiver = channel.subscribe(None, None, Vec::new());
+        let mut streams = ComponentStreams::<Health, ()>::default();
+        streams.push(channel);
+        world.insert_resource(streams);
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(publish_component_changes::<Health, ()>);
+
+        let entity = world.spawn(Health(10)).id();
+        schedule.run(&mut world);
+        world.get_mut::<Health>(entity).unwrap().0 = 5;
+        schedule.run(&mut world);
+        world.entity_mut(entity).remove::<Health>();
+        schedule.run(&mut world);
+
+        let events: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|message| (message.event.unwrap(), message.data.to_string()))
+            .collect();
+        let bits = entity.to_bits();
+        assert_eq!(
+            events,
+            vec![
+                ("added", format!(r#"{{"entity":{bits},"value":10}}"#)),
+                ("changed", format!(r#"{{"entity":{bits},"value":5}}"#)),
+                ("removed", format!(r#"{{"entity":{bits}}}"#)),
+            ]
+        );
+    }
+}
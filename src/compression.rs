@@ -0,0 +1,117 @@
+use axum::http::{header, HeaderMap};
+
+#[cfg(feature = "compression")]
+mod layer;
+
+#[cfg(feature = "compression")]
+pub use layer::CompressionConfig;
+#[cfg(feature = "compression")]
+pub(crate) use layer::CompressionLayer;
+
+/// `Content-Encoding` a response can be sent with
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ContentEncoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// Encodings of precompressed files, most effective first
+    pub(crate) const PRECOMPRESSED: [Self; 3] = [Self::Brotli, Self::Zstd, Self::Gzip];
+
+    pub(crate) fn token(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    /// Extension of a file precompressed with this encoding
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Zstd => "zst",
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// Quality the `Accept-Encoding` header in `headers` gives this encoding
+    fn quality(self, headers: &HeaderMap) -> f32 {
+        let mut wildcard = 0.0;
+        let codings = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for coding in codings {
+            let mut params = coding.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if name.eq_ignore_ascii_case(self.token())
+                || (self == Self::Gzip && name.eq_ignore_ascii_case("x-gzip"))
+            {
+                return quality;
+            }
+            if name == "*" {
+                wildcard = quality;
+            }
+        }
+        wildcard
+    }
+
+    /// Encoding of `candidates` the client accepts with the highest quality, preferring
+    /// earlier candidates on ties
+    pub(crate) fn negotiate(headers: &HeaderMap, candidates: &[Self]) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+        for &encoding in candidates {
+            let quality = encoding.quality(headers);
+            if quality > 0.0 && best.is_none_or(|(best_quality, _)| quality > best_quality) {
+                best = Some((quality, encoding));
+            }
+        }
+        best.map(|(_, encoding)| encoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn accept_encoding(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let all = ContentEncoding::PRECOMPRESSED;
+        assert_eq!(
+            ContentEncoding::negotiate(&accept_encoding("gzip, deflate, br"), &all),
+            Some(ContentEncoding::Brotli)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate(&accept_encoding("br;q=0.5, gzip"), &all),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate(&accept_encoding("*;q=0.1, br;q=0"), &all),
+            Some(ContentEncoding::Zstd)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate(&accept_encoding("identity"), &all),
+            None
+        );
+        assert_eq!(ContentEncoding::negotiate(&HeaderMap::new(), &all), None);
+    }
+}
@@ -0,0 +1,415 @@
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+use bevy_log::warn;
+use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+use flate2::{write::GzEncoder, Compression};
+use futures_util::StreamExt;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use super::ContentEncoding;
+use crate::blocking::run_on_pool;
+use crate::WebStaticFileExtensions;
+
+/// Per-server on-the-fly response compression.
+///
+/// Responses are compressed with brotli, zstd or gzip, whichever the client accepts best,
+/// when their content type is compressible according to [`WebStaticFileExtensions`] and they
+/// are at least `min_size` bytes long. Streamed responses of unknown length are always
+/// compressed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressionConfig {
+    /// Smallest body worth compressing, in bytes
+    pub min_size: u64,
+    /// Level from 0 (fastest) to 9 (smallest), used by every encoding; higher levels act as 9
+    pub level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            level: 6,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Whether `response`, answering a `method` request, should be compressed
+    fn should_compress(&self, method: &Method, response: &Response) -> bool {
+        let headers = response.headers();
+        if method == Method::HEAD
+            || response.status() == StatusCode::NO_CONTENT
+            || response.status() == StatusCode::NOT_MODIFIED
+            || response.status() == StatusCode::PARTIAL_CONTENT
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+        let no_transform = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.to_ascii_lowercase().contains("no-transform"));
+        if no_transform {
+            return false;
+        }
+
+        // Lengths are only known from the header or body once the response is final
+        let len = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok())
+            .or_else(|| response.body().size_hint().exact());
+        let too_small = len.is_some_and(|len| len < self.min_size);
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok());
+        !too_small
+            && content_type.is_some_and(|content_type| {
+                WebStaticFileExtensions::with_current(|extensions| {
+                    extensions.is_compressible_type(content_type)
+                })
+            })
+    }
+}
+
+/// Streaming encoder for one response body
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Gzip(GzEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    /// Window size brotli uses, as the base-2 logarithm of its bytes
+    const BROTLI_WINDOW: u32 = 22;
+
+    fn new(encoding: ContentEncoding, level: u32) -> std::io::Result<Self> {
+        // The field is public, so it may not have gone through `with_level`
+        let level = level.min(9);
+        Ok(match encoding {
+            ContentEncoding::Brotli => Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                level,
+                Self::BROTLI_WINDOW,
+            ))),
+            // zstd levels go up to 22, but past 9 they cost far more time than they save bytes
+            ContentEncoding::Zstd => Self::Zstd(zstd::stream::write::Encoder::new(
+                Vec::new(),
+                level.max(1) as i32,
+            )?),
+            ContentEncoding::Gzip => {
+                Self::Gzip(GzEncoder::new(Vec::new(), Compression::new(level)))
+            }
+        })
+    }
+
+    /// Compress `data` and flush it, returning the compressed bytes produced so far
+    fn write_chunk(&mut self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let output = match self {
+            Self::Brotli(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Self::Zstd(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Self::Gzip(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output))
+    }
+
+    /// Finish the stream, returning its last bytes
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Brotli(encoder) => Ok(encoder.into_inner()),
+            Self::Zstd(encoder) => encoder.finish(),
+            Self::Gzip(encoder) => encoder.finish(),
+        }
+    }
+}
+
+/// Run `work` on the [`AsyncComputeTaskPool`], so compressing a large body never stalls
+/// the executor polling the response, which is the app's own in [`IoMode::Main`](crate::IoMode::Main)
+async fn off_executor<T: Send + 'static>(
+    work: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> std::io::Result<T> {
+    run_on_pool(AsyncComputeTaskPool::get_or_init(TaskPool::default), work)
+        .await
+        .unwrap_or_else(|| Err(std::io::Error::other("compression panicked")))
+}
+
+/// Body compressing `body` as it streams, flushing after every chunk so streamed responses
+/// keep flowing
+fn compress_body(body: Body, encoder: Encoder) -> Body {
+    let chunks = futures_util::stream::unfold(
+        Some((body.into_data_stream(), encoder)),
+        |state| async move {
+            let (mut stream, mut encoder) = state?;
+            loop {
+                let compressed = match stream.next().await {
+                    Some(Ok(data)) => {
+                        off_executor(move || {
+                            let compressed = encoder.write_chunk(&data)?;
+                            Ok((encoder, compressed))
+                        })
+                        .await
+                    }
+                    Some(Err(err)) => return Some((Err(std::io::Error::other(err)), None)),
+                    None => {
+                        let last = off_executor(move || encoder.finish()).await;
+                        return Some((last.map(Bytes::from), None));
+                    }
+                };
+                match compressed {
+                    Ok((next, compressed)) => {
+                        encoder = next;
+                        if !compressed.is_empty() {
+                            return Some((Ok(Bytes::from(compressed)), Some((stream, encoder))));
+                        }
+                    }
+                    Err(err) => return Some((Err(err), None)),
+                }
+            }
+        },
+    );
+    Body::from_stream(chunks)
+}
+
+/// Tower layer compressing responses according to a [`CompressionConfig`]
+#[derive(Clone)]
+pub(crate) struct CompressionLayer {
+    config: CompressionConfig,
+}
+
+impl CompressionLayer {
+    pub(crate) fn new(config: CompressionConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = CompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CompressionService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CompressionService<S> {
+    inner: S,
+    config: CompressionConfig,
+}
+
+impl<S> Service<Request> for CompressionService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let encoding =
+            ContentEncoding::negotiate(request.headers(), &ContentEncoding::PRECOMPRESSED);
+        let method = request.method().clone();
+        let config = self.config.clone();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            let Some(encoding) = encoding else {
+                return Ok(response);
+            };
+            if !config.should_compress(&method, &response) {
+                return Ok(response);
+            }
+            let encoder = match Encoder::new(encoding, config.level) {
+                Ok(encoder) => encoder,
+                Err(err) => {
+                    warn!("Failed to start {} compression: {}", encoding.token(), err);
+                    return Ok(response);
+                }
+            };
+
+            let (mut parts, body) = response.into_parts();
+            let headers = &mut parts.headers;
+            headers.remove(header::CONTENT_LENGTH);
+            headers.remove(header::ACCEPT_RANGES);
+            headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.token()),
+            );
+            if !varies_by_encoding(headers) {
+                headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+            }
+            // The compressed bytes differ from those the strong ETag describes
+            if let Some(etag) = headers
+                .get(header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+            {
+                if !etag.starts_with("W/") {
+                    if let Ok(weak) = HeaderValue::from_str(&format!("W/{etag}")) {
+                        headers.insert(header::ETAG, weak);
+                    }
+                }
+            }
+            Ok(Response::from_parts(parts, compress_body(body, encoder)))
+        })
+    }
+}
+
+/// Whether `headers` already hold a `Vary` covering `Accept-Encoding`
+fn varies_by_encoding(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("accept-encoding"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tower::ServiceExt;
+
+    fn accept_encoding(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_compresses_large_text_responses() {
+        let page = "<p>hello</p>".repeat(200);
+        let small = page[..100].to_string();
+        let router = Router::new()
+            .route(
+                "/page",
+                get(move || async move { axum::response::Html(page) }),
+            )
+            .route(
+                "/small",
+                get(move || async move { axum::response::Html(small) }),
+            )
+            .route(
+                "/varied",
+                get(|| async {
+                    (
+                        [
+                            (header::CONTENT_TYPE, "text/css"),
+                            (header::VARY, "Accept-Encoding"),
+                        ],
+                        "body {}".repeat(500),
+                    )
+                }),
+            )
+            .route(
+                "/image",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png")], vec![0u8; 4096]) }),
+            )
+            .layer(CompressionLayer::new(CompressionConfig::default()));
+
+        let call = |path: &str, encoding: &str| {
+            let mut request = Request::get(path).body(Body::empty()).unwrap();
+            *request.headers_mut() = accept_encoding(encoding);
+            let response = async_io::block_on(router.clone().oneshot(request)).unwrap();
+            let (parts, body) = response.into_parts();
+            let body = async_io::block_on(axum::body::to_bytes(body, usize::MAX)).unwrap();
+            (parts.headers, body)
+        };
+
+        let (headers, body) = call("/page", "gzip");
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(headers[header::VARY], "accept-encoding");
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "<p>hello</p>".repeat(200));
+
+        let (headers, body) = call("/page", "gzip;q=0.5, br");
+        assert_eq!(headers[header::CONTENT_ENCODING], "br");
+        let mut decompressed = String::new();
+        brotli::Decompressor::new(&body[..], 4096)
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "<p>hello</p>".repeat(200));
+
+        let (headers, body) = call("/page", "zstd, gzip;q=0.8");
+        assert_eq!(headers[header::CONTENT_ENCODING], "zstd");
+        assert_eq!(
+            zstd::decode_all(&body[..]).unwrap(),
+            "<p>hello</p>".repeat(200).as_bytes()
+        );
+
+        let (headers, _) = call("/page", "identity");
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+        let (headers, _) = call("/varied", "gzip");
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(headers.get_all(header::VARY).iter().count(), 1);
+        let (headers, _) = call("/small", "gzip");
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+        let (headers, _) = call("/image", "gzip");
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[test]
+    fn test_out_of_range_level_is_clamped() {
+        let page = "<p>hello</p>".repeat(200);
+        for encoding in [
+            ContentEncoding::Brotli,
+            ContentEncoding::Zstd,
+            ContentEncoding::Gzip,
+        ] {
+            let mut encoder = Encoder::new(encoding, 42).unwrap();
+            let mut body = encoder.write_chunk(page.as_bytes()).unwrap();
+            body.extend(encoder.finish().unwrap());
+            assert!(body.len() < page.len());
+        }
+    }
+}
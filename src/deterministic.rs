@@ -0,0 +1,198 @@
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    response::Response,
+};
+use bevy_app::App;
+use bevy_defer::systems::run_async_executor;
+use bevy_ecs::{
+    prelude::*,
+    schedule::{InternedScheduleLabel, ScheduleLabel},
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use crate::HttpErrorResponses;
+
+/// Sender half a held request waits on until its schedule releases it
+type Release = async_channel::Sender<()>;
+
+/// Queues of held requests, one per schedule that releases them
+#[derive(Default, Resource)]
+pub(crate) struct DeterministicQueues {
+    queues: HashMap<InternedScheduleLabel, async_channel::Sender<Release>>,
+}
+
+/// Layer for a server whose mutating requests are released in `schedule`,
+/// adding the releasing system the first time the schedule is used
+pub(crate) fn deterministic_layer(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+) -> DeterministicLayer {
+    let schedule = schedule.intern();
+    let existing = app
+        .world_mut()
+        .get_resource_or_init::<DeterministicQueues>()
+        .queues
+        .get(&schedule)
+        .cloned();
+
+    let queue = match existing {
+        Some(queue) => queue,
+        None => {
+            let (sender, receiver) = async_channel::unbounded();
+            app.world_mut()
+                .resource_mut::<DeterministicQueues>()
+                .queues
+                .insert(schedule, sender.clone());
+            app.add_systems(schedule, move |world: &mut World| {
+                release_held_requests(world, &receiver)
+            });
+            sender
+        }
+    };
+
+    DeterministicLayer { queue }
+}
+
+/// Release the requests held so far one by one in arrival order, running the async executor
+/// after each so its handler makes all the progress it can before the next one starts
+pub(crate) fn release_held_requests(world: &mut World, queue: &async_channel::Receiver<Release>) {
+    // Only what arrived before this run, requests arriving meanwhile wait for the next one
+    for _ in 0..queue.len() {
+        let Ok(release) = queue.try_recv() else {
+            break;
+        };
+        if release.try_send(()).is_ok() {
+            run_async_executor(world);
+        }
+    }
+}
+
+/// Requests with these methods only read the world and are never held
+fn is_safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Tower layer holding mutating requests until their schedule releases them
+#[derive(Clone, Debug)]
+pub(crate) struct DeterministicLayer {
+    queue: async_channel::Sender<Release>,
+}
+
+impl<S> Layer<S> for DeterministicLayer {
+    type Service = DeterministicService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DeterministicService {
+            inner,
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct DeterministicService<S> {
+    inner: S,
+    queue: async_channel::Sender<Release>,
+}
+
+impl<S> Service<Request> for DeterministicService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        if is_safe_method(request.method()) {
+            return Box::pin(self.inner.call(request));
+        }
+
+        // Take the service that was polled ready, leaving a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let queue = self.queue.clone();
+
+        Box::pin(async move {
+            let (release, released) = async_channel::bounded(1);
+            if queue.send(release).await.is_err() || released.recv().await.is_err() {
+                return Ok(HttpErrorResponses::respond(StatusCode::SERVICE_UNAVAILABLE));
+            }
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{WebServerAppExt, WebServerManager};
+    use axum::{body::Body, routing::post};
+    use bevy_app::PostUpdate;
+    use bevy_defer::{AsyncAccess, AsyncExecutor, AsyncPlugin, AsyncWorld};
+    use tower::ServiceExt;
+
+    #[derive(Default, Resource)]
+    struct Applied(Vec<u32>);
+
+    #[test]
+    fn test_held_requests_are_released_in_order() {
+        let mut app = App::new();
+        app.add_plugins((bevy::MinimalPlugins, AsyncPlugin::default_settings()))
+            .init_resource::<Applied>()
+            .port_route(
+                29185,
+                "/apply",
+                post(|body: String| async move {
+                    let value = body.parse().unwrap();
+                    AsyncWorld
+                        .resource::<Applied>()
+                        .get_mut(|applied| applied.0.push(value))
+                        .unwrap();
+                }),
+            )
+            .port_deterministic(29185, PostUpdate);
+
+        let router = app
+            .world()
+            .resource::<WebServerManager>()
+            .get_server(&29185)
+            .unwrap()
+            .service_router();
+        for value in [2, 1] {
+            let request = Request::post("/apply")
+                .body(Body::from(value.to_string()))
+                .unwrap();
+            app.world()
+                .non_send_resource::<AsyncExecutor>()
+                .spawn_any(router.clone().oneshot(request));
+            // Let the request arrive, which would apply it if it weren't held
+            run_async_executor(app.world_mut());
+        }
+        assert!(app.world().resource::<Applied>().0.is_empty());
+
+        app.update();
+        assert_eq!(app.world().resource::<Applied>().0, [2, 1]);
+    }
+
+    #[test]
+    fn test_safe_methods_are_not_held() {
+        assert!(is_safe_method(&Method::GET));
+        assert!(is_safe_method(&Method::HEAD));
+        assert!(!is_safe_method(&Method::POST));
+        assert!(!is_safe_method(&Method::DELETE));
+    }
+}
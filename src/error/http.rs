@@ -1,18 +1,29 @@
-use axum::{http::StatusCode, response::Response};
-use bevy_app::{App, Plugin};
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use bevy_app::{App, Plugin, Update};
+use bevy_defer::{AsyncAccess, AsyncWorld};
 use bevy_derive::{Deref, DerefMut};
 use bevy_ecs::prelude::*;
 use std::collections::HashMap;
 
+use super::template::{escape_html, reload_error_templates, ErrorContext, ErrorTemplate};
+use super::ErrorFormat;
+
 pub struct HttpErrorPlugin;
 
 impl Plugin for HttpErrorPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<HttpErrorResponses>();
+        app.init_resource::<HttpErrorResponses>()
+            .add_systems(Update, reload_error_templates);
     }
 }
 
-fn create_error_html(code: &str, title: &str, message: &str) -> String {
+fn create_error_html(code: &str, title: &str, message: &str, detail: Option<&str>) -> String {
+    let detail = detail
+        .map(|detail| format!(r#"<p class="error-detail">{}</p>"#, escape_html(detail)))
+        .unwrap_or_default();
     format!(
         r#"
 <!DOCTYPE html>
@@ -111,6 +122,14 @@ fn create_error_html(code: &str, title: &str, message: &str) -> String {
             font-weight: 400;
             line-height: 1.6;
         }}
+        .error-detail {{
+            font-size: 1rem;
+            color: #e6edf3;
+            margin-top: -1rem;
+            margin-bottom: 2rem;
+            line-height: 1.6;
+            word-break: break-word;
+        }}
         .back-button {{
             background: linear-gradient(135deg, #ff6b35 0%, #ff8c42 100%);
             color: white;
@@ -174,6 +193,7 @@ fn create_error_html(code: &str, title: &str, message: &str) -> String {
             <h1 class="error-code">{code}</h1>
             <h2 class="error-title">{title}</h2>
             <p class="error-message">{message}</p>
+            {detail}
             <button class="back-button" onclick="history.back()">Go Back</button>
         </div>
         
@@ -186,13 +206,115 @@ fn create_error_html(code: &str, title: &str, message: &str) -> String {
     "#,
         code = code,
         title = title,
-        message = message
+        message = message,
+        detail = detail
+    )
+}
+
+/// `status` if it is a client or server error, 500 otherwise
+pub(crate) fn error_status(status: StatusCode) -> StatusCode {
+    if status.is_client_error() || status.is_server_error() {
+        status
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Fallback of managed routers for unknown paths
+pub(crate) async fn not_found_fallback() -> Response {
+    HttpErrorResponses::respond(StatusCode::NOT_FOUND)
+}
+
+/// Fallback of managed routers for known paths requested with an unsupported method
+pub(crate) async fn method_not_allowed_fallback() -> Response {
+    HttpErrorResponses::respond(StatusCode::METHOD_NOT_ALLOWED)
+}
+
+/// Marks a response as an error page, so it can be rendered again in the format the client accepts
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ErrorPage {
+    pub(crate) detail: Option<String>,
+}
+
+/// Built-in HTML page for `status`, showing `detail` below its message
+fn built_in_html(status: StatusCode, detail: Option<&str>) -> String {
+    create_error_html(
+        &status.as_u16().to_string(),
+        status.canonical_reason().unwrap_or("Error"),
+        default_message(status),
+        detail,
     )
 }
 
-#[derive(Clone, Deref, DerefMut, Resource)]
+/// Message shown below the title of the built-in page for `status`
+fn default_message(status: StatusCode) -> &'static str {
+    DEFAULT_PAGES
+        .iter()
+        .find(|(page_status, ..)| *page_status == status)
+        .map_or("An error occurred.", |(_, _, message)| message)
+}
+
+/// RFC 9457 problem details sent to clients that accept `application/problem+json`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProblemDetails {
+    /// URI identifying the problem type, `about:blank` when the status says it all
+    pub problem_type: String,
+    pub title: String,
+    pub detail: Option<String>,
+    /// Extension members added next to the standard ones
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ProblemDetails {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank".to_string(),
+            title: title.into(),
+            detail: None,
+            extensions: serde_json::Map::new(),
+        }
+    }
+
+    pub fn with_type(mut self, problem_type: impl Into<String>) -> Self {
+        self.problem_type = problem_type.into();
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_extension(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.extensions.insert(key.into(), value);
+        self
+    }
+
+    /// JSON document for a response with `status`
+    pub fn to_json(&self, status: StatusCode) -> serde_json::Value {
+        let mut object = self.extensions.clone();
+        object.insert("type".into(), self.problem_type.clone().into());
+        object.insert("title".into(), self.title.clone().into());
+        object.insert("status".into(), status.as_u16().into());
+        if let Some(detail) = &self.detail {
+            object.insert("detail".into(), detail.clone().into());
+        }
+        serde_json::Value::Object(object)
+    }
+}
+
+/// Error pages served by the web servers, in every format clients can ask for.
+///
+/// Dereferences to the HTML pages by status code. Clients preferring JSON receive
+/// [`ProblemDetails`] and clients preferring plain text a short message, see [`ErrorFormat`].
+#[derive(Clone, Debug, Deref, DerefMut, Resource)]
 pub struct HttpErrorResponses {
+    #[deref]
     responses: HashMap<StatusCode, String>,
+    text: HashMap<StatusCode, String>,
+    problems: HashMap<StatusCode, ProblemDetails>,
+    templates: HashMap<StatusCode, ErrorTemplate>,
+    default_template: Option<ErrorTemplate>,
 }
 
 impl HttpErrorResponses {
@@ -201,79 +323,345 @@ impl HttpErrorResponses {
     }
 
     pub fn get_response_or_default(&self, status: StatusCode) -> String {
-        self.responses.get(&status).cloned().unwrap_or_else(|| {
-            create_error_html(
-                &status.as_u16().to_string(),
-                status.canonical_reason().unwrap_or("Error"),
-                "An error occurred.",
+        self.responses
+            .get(&status)
+            .cloned()
+            .unwrap_or_else(|| built_in_html(status, None))
+    }
+
+    /// HTML page for `status`, with `detail` below the message if it is the built-in page
+    fn get_response_with_detail(&self, status: StatusCode, detail: Option<&str>) -> String {
+        let page = self.get_response_or_default(status);
+        match detail {
+            Some(detail) if page == built_in_html(status, None) => {
+                built_in_html(status, Some(detail))
+            }
+            _ => page,
+        }
+    }
+
+    /// Plain text body for `status`
+    pub fn get_text_or_default(&self, status: StatusCode) -> String {
+        self.text.get(&status).cloned().unwrap_or_else(|| {
+            format!(
+                "{} {}\n",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or("Error")
             )
         })
     }
 
+    /// Problem details for `status`
+    pub fn get_problem_or_default(&self, status: StatusCode) -> ProblemDetails {
+        self.problems
+            .get(&status)
+            .cloned()
+            .unwrap_or_else(|| ProblemDetails::new(status.canonical_reason().unwrap_or("Error")))
+    }
+
+    pub fn set_html(&mut self, status: StatusCode, html: impl Into<String>) -> &mut Self {
+        self.responses.insert(status, html.into());
+        self
+    }
+
+    pub fn set_text(&mut self, status: StatusCode, text: impl Into<String>) -> &mut Self {
+        self.text.insert(status, text.into());
+        self
+    }
+
+    pub fn set_problem(&mut self, status: StatusCode, problem: ProblemDetails) -> &mut Self {
+        self.problems.insert(status, problem);
+        self
+    }
+
+    /// Render the HTML page of `status` from `template` instead of the built-in style
+    pub fn set_template(&mut self, status: StatusCode, template: ErrorTemplate) -> &mut Self {
+        self.templates.insert(status, template);
+        self
+    }
+
+    /// Render the HTML page of every status without its own template from `template`
+    pub fn set_default_template(&mut self, template: ErrorTemplate) -> &mut Self {
+        self.default_template = Some(template);
+        self
+    }
+
+    /// Template rendering the HTML page of `status`, if any
+    pub fn get_template(&self, status: StatusCode) -> Option<&ErrorTemplate> {
+        self.templates
+            .get(&status)
+            .or(self.default_template.as_ref())
+    }
+
+    pub(crate) fn templates(&self) -> impl Iterator<Item = &ErrorTemplate> {
+        self.templates.values().chain(&self.default_template)
+    }
+
+    /// HTML page for `status` rendered for the request in `context`.
+    ///
+    /// The detail of `context` is shown, escaped, by templates and the built-in page;
+    /// pages set with [`set_html`](Self::set_html) are sent as they are.
+    pub fn get_html_with(&self, status: StatusCode, context: &ErrorContext) -> String {
+        self.get_template(status)
+            .and_then(|template| template.render(status, default_message(status), context))
+            .unwrap_or_else(|| self.get_response_with_detail(status, context.detail.as_deref()))
+    }
+
+    /// HTML response for `status`
     pub fn create_response(&self, status: StatusCode) -> Response {
-        let html = self.get_response_or_default(status);
-        Response::builder()
+        self.create_response_as(status, ErrorFormat::Html)
+    }
+
+    /// Response for `status` in the format preferred by the `Accept` header in `headers`
+    pub fn create_negotiated_response(&self, status: StatusCode, headers: &HeaderMap) -> Response {
+        self.create_response_as(status, ErrorFormat::negotiate(headers))
+    }
+
+    pub fn create_response_as(&self, status: StatusCode, format: ErrorFormat) -> Response {
+        self.create_response_with(status, format, &ErrorContext::default())
+    }
+
+    /// Response for `status` in `format`, filling in details of the request in `context`.
+    ///
+    /// Statuses that are not client or server errors are answered with 500 instead, so an
+    /// error page never goes out as a success or a redirect.
+    pub fn create_response_with(
+        &self,
+        status: StatusCode,
+        format: ErrorFormat,
+        context: &ErrorContext,
+    ) -> Response {
+        let status = error_status(status);
+        let (content_type, body) = match format {
+            ErrorFormat::Html => ("text/html", self.get_html_with(status, context)),
+            ErrorFormat::Text => {
+                let mut text = self.get_text_or_default(status);
+                if let Some(detail) = &context.detail {
+                    text.push_str(detail);
+                    text.push('\n');
+                }
+                ("text/plain; charset=utf-8", text)
+            }
+            ErrorFormat::ProblemJson => {
+                let mut problem = self.get_problem_or_default(status);
+                if let Some(detail) = &context.detail {
+                    problem.detail = Some(detail.clone());
+                }
+                let mut json = problem.to_json(status);
+                if let Some(path) = &context.path {
+                    json["instance"] = path.clone().into();
+                }
+                if let Some(request_id) = &context.request_id {
+                    json["request_id"] = request_id.clone().into();
+                }
+                ("application/problem+json", json.to_string())
+            }
+        };
+
+        let mut response = Response::builder()
             .status(status)
-            .header("Content-Type", "text/html")
-            .body(html.to_string().into())
-            .unwrap_or_default()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body.into())
+            .unwrap_or_default();
+        response.extensions_mut().insert(ErrorPage {
+            detail: context.detail.clone(),
+        });
+        response
+    }
+
+    /// Render `status` from inside a handler using the app's `HttpErrorResponses`,
+    /// falling back to the built-in pages if the resource is missing or the world is unavailable.
+    ///
+    /// The page is rendered again in the format the client accepts before it is sent.
+    pub fn respond(status: StatusCode) -> Response {
+        if !bevy_defer::in_async_context() {
+            return HttpErrorResponses::default().create_response(status);
+        }
+        AsyncWorld
+            .resource::<HttpErrorResponses>()
+            .get(|responses| responses.create_response(status))
+            .unwrap_or_else(|_| HttpErrorResponses::default().create_response(status))
+    }
+
+    /// Like [`respond`](Self::respond), with `detail` explaining this particular error.
+    /// The built-in page shows it escaped below its message, templates as `{{detail}}` and
+    /// problem details as their `detail` member; pages set with [`set_html`](Self::set_html) don't.
+    pub fn respond_with_detail(status: StatusCode, detail: impl Into<String>) -> Response {
+        let context = ErrorContext::default().with_detail(detail);
+        if !bevy_defer::in_async_context() {
+            return HttpErrorResponses::default().create_response_with(
+                status,
+                ErrorFormat::Html,
+                &context,
+            );
+        }
+        AsyncWorld
+            .resource::<HttpErrorResponses>()
+            .get(|responses| responses.create_response_with(status, ErrorFormat::Html, &context))
+            .unwrap_or_else(|_| {
+                HttpErrorResponses::default().create_response_with(
+                    status,
+                    ErrorFormat::Html,
+                    &context,
+                )
+            })
     }
 }
 
+/// Status, title and message of the built-in pages
+const DEFAULT_PAGES: [(StatusCode, &str, &str); 12] = [
+    (
+        StatusCode::BAD_REQUEST,
+        "Bad Request",
+        "Sorry, we couldn't process your request.",
+    ),
+    (
+        StatusCode::UNAUTHORIZED,
+        "Unauthorized",
+        "Sorry, you are not authorized to access this resource.",
+    ),
+    (
+        StatusCode::FORBIDDEN,
+        "Forbidden",
+        "Sorry, you don't have permission to access this resource.",
+    ),
+    (
+        StatusCode::NOT_FOUND,
+        "Not Found",
+        "Sorry, we couldn't find that page.",
+    ),
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        "Method Not Allowed",
+        "Sorry, this page doesn't support that kind of request.",
+    ),
+    (
+        StatusCode::REQUEST_TIMEOUT,
+        "Request Timeout",
+        "Sorry, your request took too long to arrive.",
+    ),
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "Payload Too Large",
+        "Sorry, your request is larger than we can accept.",
+    ),
+    (
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        "Range Not Satisfiable",
+        "Sorry, the requested part of this file doesn't exist.",
+    ),
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too Many Requests",
+        "Sorry, you're sending requests too quickly. Please try again later.",
+    ),
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal Server Error",
+        "Something went wrong on our end.",
+    ),
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Service Unavailable",
+        "The service is temporarily unavailable.",
+    ),
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        "Gateway Timeout",
+        "The server took too long to respond.",
+    ),
+];
+
 impl Default for HttpErrorResponses {
     fn default() -> Self {
         let mut responses = HashMap::new();
+        let mut text = HashMap::new();
+        let mut problems = HashMap::new();
 
-        responses.insert(
-            StatusCode::BAD_REQUEST,
-            create_error_html(
-                "400",
-                "Bad Request",
-                "Sorry, we couldn't process your request.",
-            ),
-        );
+        for (status, title, message) in DEFAULT_PAGES {
+            let code = status.as_u16();
+            responses.insert(status, built_in_html(status, None));
+            text.insert(status, format!("{code} {title}\n{message}\n"));
+            problems.insert(status, ProblemDetails::new(title).with_detail(message));
+        }
 
-        responses.insert(
-            StatusCode::UNAUTHORIZED,
-            create_error_html(
-                "401",
-                "Unauthorized",
-                "Sorry, you are not authorized to access this resource.",
-            ),
-        );
+        Self {
+            responses,
+            text,
+            problems,
+            templates: HashMap::new(),
+            default_template: None,
+        }
+    }
+}
 
-        responses.insert(
-            StatusCode::FORBIDDEN,
-            create_error_html(
-                "403",
-                "Forbidden",
-                "Sorry, you don't have permission to access this resource.",
-            ),
-        );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        responses.insert(
+    #[test]
+    fn test_representations() {
+        let mut responses = HttpErrorResponses::default();
+        responses.set_problem(
             StatusCode::NOT_FOUND,
-            create_error_html("404", "Not Found", "Sorry, we couldn't find that page."),
+            ProblemDetails::new("Unknown player")
+                .with_type("https://example.com/problems/unknown-player")
+                .with_extension("player", "alice".into()),
+        );
+
+        let problem = responses
+            .get_problem_or_default(StatusCode::NOT_FOUND)
+            .to_json(StatusCode::NOT_FOUND);
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["title"], "Unknown player");
+        assert_eq!(problem["player"], "alice");
+        assert!(problem.get("detail").is_none());
+
+        let problem = responses
+            .get_problem_or_default(StatusCode::BAD_GATEWAY)
+            .to_json(StatusCode::BAD_GATEWAY);
+        assert_eq!(problem["type"], "about:blank");
+        assert_eq!(problem["title"], "Bad Gateway");
+
+        assert_eq!(
+            responses.get_text_or_default(StatusCode::NOT_FOUND),
+            "404 Not Found\nSorry, we couldn't find that page.\n"
         );
 
-        responses.insert(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            create_error_html(
-                "500",
-                "Internal Server Error",
-                "Something went wrong on our end.",
-            ),
+        let response = responses.create_response_as(StatusCode::NOT_FOUND, ErrorFormat::Text);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
         );
+        assert!(response.extensions().get::<ErrorPage>().is_some());
+    }
 
-        responses.insert(
-            StatusCode::SERVICE_UNAVAILABLE,
-            create_error_html(
-                "503",
-                "Service Unavailable",
-                "The service is temporarily unavailable.",
-            ),
+    #[test]
+    fn test_non_error_statuses_become_500() {
+        let responses = HttpErrorResponses::default();
+        for status in [StatusCode::OK, StatusCode::FOUND, StatusCode::CONTINUE] {
+            let response = responses.create_response_as(status, ErrorFormat::ProblemJson);
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert_eq!(
+            responses.create_response(StatusCode::IM_A_TEAPOT).status(),
+            StatusCode::IM_A_TEAPOT
         );
+    }
 
-        Self { responses }
+    #[test]
+    fn test_default_page_shows_detail() {
+        let responses = HttpErrorResponses::default();
+        let context = ErrorContext::default().with_detail("<script>alert(1)</script>");
+        let html = responses.get_html_with(StatusCode::BAD_REQUEST, &context);
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+
+        let mut responses = HttpErrorResponses::default();
+        responses.set_html(StatusCode::BAD_REQUEST, "<h1>Bad</h1>");
+        assert_eq!(
+            responses.get_html_with(StatusCode::BAD_REQUEST, &context),
+            "<h1>Bad</h1>"
+        );
     }
 }
@@ -1,8 +1,18 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use std::net::IpAddr;
 use thiserror::Error;
 
 pub mod http;
+mod negotiate;
+mod template;
+
 pub use http::*;
+pub use negotiate::ErrorFormat;
+pub(crate) use negotiate::{ErrorNegotiationLayer, MatchedRouteLayer};
+#[cfg(feature = "asset")]
+pub use template::asset::{ErrorTemplateAsset, ErrorTemplateAssetPlugin};
+pub use template::{ErrorContext, ErrorTemplate};
 
 pub type WebServerResult<T> = Result<T, WebServerError>;
 
@@ -104,6 +114,44 @@ impl WebServerError {
             details: details.into(),
         }
     }
+
+    /// HTTP status a handler returning this error answers with, 500 for an `HttpError`
+    /// whose status is not a client or server error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::HttpError { status, .. } => StatusCode::from_u16(*status)
+                .map(error_status)
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::AuthError { .. } => StatusCode::UNAUTHORIZED,
+            Self::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            Self::ResourceExhausted { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::BindFailed { .. }
+            | Self::ServerNotFound { .. }
+            | Self::ServerAlreadyRunning { .. }
+            | Self::IoError { .. }
+            | Self::ConfigError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Answers with the [`HttpErrorResponses`] page for [`WebServerError::status_code`], so handlers
+/// can return `WebServerResult<T>` and use `?` on `bevy_defer::AccessError`s.
+///
+/// Messages of `HttpError` and `AuthError` are shown to the client as the page's detail;
+/// other errors may describe internals and are only logged.
+impl IntoResponse for WebServerError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        match self {
+            Self::HttpError { message, .. } | Self::AuthError { reason: message } => {
+                HttpErrorResponses::respond_with_detail(status, message)
+            }
+            error => {
+                bevy_log::error!("Handler failed: {}", error);
+                HttpErrorResponses::respond(status)
+            }
+        }
+    }
 }
 
 impl From<bevy_defer::AccessError> for WebServerError {
@@ -202,3 +250,52 @@ impl From<bevy_defer::AccessError> for WebServerError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            WebServerError::auth_error("bad token").status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            WebServerError::timeout("query", 500).status_code(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert_eq!(
+            WebServerError::http_error(418, "short and stout").status_code(),
+            StatusCode::IM_A_TEAPOT
+        );
+        assert_eq!(
+            WebServerError::http_error(42, "not a status").status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            WebServerError::http_error(200, "not an error").status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let error: WebServerError =
+            bevy_defer::AccessError::ResourceNotFound { name: "Score" }.into();
+        assert_eq!(error.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_into_response() {
+        let response = WebServerError::auth_error("bad token").into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response
+            .extensions()
+            .get::<ErrorPage>()
+            .is_some_and(|page| page.detail.as_deref() == Some("bad token")));
+
+        let response = WebServerError::config_error("db", "secret path").into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(response
+            .extensions()
+            .get::<ErrorPage>()
+            .is_some_and(|page| page.detail.is_none()));
+    }
+}
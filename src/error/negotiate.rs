@@ -0,0 +1,247 @@
+use axum::{
+    body::HttpBody,
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use bevy_defer::{AsyncAccess, AsyncWorld};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use super::http::{ErrorPage, HttpErrorResponses};
+use super::template::ErrorContext;
+
+/// Representation of an error response
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorFormat {
+    /// Styled `text/html` page, also sent when the client has no preference
+    #[default]
+    Html,
+    /// RFC 9457 `application/problem+json`
+    ProblemJson,
+    /// Short `text/plain` message
+    Text,
+}
+
+impl ErrorFormat {
+    /// Format a single media range of an `Accept` header asks for
+    fn from_media_range(range: &str) -> Option<Self> {
+        match range {
+            "text/html" | "application/xhtml+xml" | "text/*" | "*/*" => Some(Self::Html),
+            "application/problem+json" | "application/json" => Some(Self::ProblemJson),
+            "text/plain" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// Format the `Accept` header in `headers` prefers, using quality values and
+    /// the header's order to break ties
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let mut best: Option<(f32, Self)> = None;
+
+        let ranges = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for range in ranges {
+            let mut params = range.split(';');
+            let media_range = params
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let Some(format) = Self::from_media_range(&media_range) else {
+                continue;
+            };
+            if quality > 0.0 && best.is_none_or(|(best_quality, _)| quality > best_quality) {
+                best = Some((quality, format));
+            }
+        }
+
+        best.map(|(_, format)| format).unwrap_or_default()
+    }
+}
+
+/// Tower layer rendering error pages again in the format the client accepts and with
+/// the request's details, using the server's own [`HttpErrorResponses`] if it has them.
+///
+/// The empty 404 axum sends for unknown paths of routers without a fallback is answered
+/// with the 404 page too, while 404s returned by routed handlers are left alone.
+#[derive(Clone)]
+pub(crate) struct ErrorNegotiationLayer {
+    responses: Option<HttpErrorResponses>,
+}
+
+impl ErrorNegotiationLayer {
+    pub(crate) fn new(responses: Option<HttpErrorResponses>) -> Self {
+        Self { responses }
+    }
+}
+
+impl<S> Layer<S> for ErrorNegotiationLayer {
+    type Service = ErrorNegotiationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorNegotiationService {
+            inner,
+            responses: self.responses.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ErrorNegotiationService<S> {
+    inner: S,
+    responses: Option<HttpErrorResponses>,
+}
+
+impl<S> Service<Request> for ErrorNegotiationService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let format = ErrorFormat::negotiate(request.headers());
+        let mut context = ErrorContext::from_request(&request);
+        let responses = self.responses.clone();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            context.detail = match response.extensions().get::<ErrorPage>() {
+                Some(page) => page.detail.clone(),
+                None if is_default_not_found(&response) => None,
+                None => return Ok(response),
+            };
+
+            let status = response.status();
+            let render = |responses: &HttpErrorResponses| {
+                responses.create_response_with(status, format, &context)
+            };
+            let rendered = match responses {
+                Some(responses) => render(&responses),
+                None if bevy_defer::in_async_context() => AsyncWorld
+                    .resource::<HttpErrorResponses>()
+                    .get(render)
+                    .unwrap_or_else(|_| render(&HttpErrorResponses::default())),
+                None => render(&HttpErrorResponses::default()),
+            };
+
+            // Keep headers such as `Allow` set on the original page
+            let (mut parts, _) = response.into_parts();
+            let (rendered_parts, body) = rendered.into_parts();
+            parts.headers.remove(header::CONTENT_LENGTH);
+            if let Some(content_type) = rendered_parts.headers.get(header::CONTENT_TYPE) {
+                parts
+                    .headers
+                    .insert(header::CONTENT_TYPE, HeaderValue::clone(content_type));
+            }
+            Ok(Response::from_parts(parts, body))
+        })
+    }
+}
+
+/// Whether `response` is the empty 404 of a router without a fallback, rather than
+/// a 404 a handler returned on purpose
+fn is_default_not_found(response: &Response) -> bool {
+    response.status() == StatusCode::NOT_FOUND
+        && response.extensions().get::<MatchedRoute>().is_none()
+        && response.body().size_hint().exact() == Some(0)
+}
+
+/// Set on the responses of a router's routes, as opposed to those of its fallback
+#[derive(Clone, Copy, Debug)]
+struct MatchedRoute;
+
+/// Route layer marking responses with [`MatchedRoute`], so error negotiation only
+/// renders the empty 404s of unknown paths
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MatchedRouteLayer;
+
+impl<S> Layer<S> for MatchedRouteLayer {
+    type Service = MatchedRouteService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MatchedRouteService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MatchedRouteService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for MatchedRouteService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = future.await?;
+            response.extensions_mut().insert(MatchedRoute);
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(ErrorFormat::negotiate(&HeaderMap::new()), ErrorFormat::Html);
+        assert_eq!(
+            ErrorFormat::negotiate(&accept("application/json")),
+            ErrorFormat::ProblemJson
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&accept("text/plain, */*;q=0.1")),
+            ErrorFormat::Text
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&accept("text/html;q=0.5, application/problem+json;q=0.9")),
+            ErrorFormat::ProblemJson
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&accept("text/html,application/xhtml+xml,*/*;q=0.8")),
+            ErrorFormat::Html
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&accept("application/json;q=0, image/png")),
+            ErrorFormat::Html
+        );
+    }
+}
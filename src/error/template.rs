@@ -0,0 +1,383 @@
+use axum::{extract::Request, http::Method, http::StatusCode};
+use bevy_ecs::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::http::HttpErrorResponses;
+use crate::{RequestContext, WebServerManager};
+
+/// How often template files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Request an error page is rendered for, filling the placeholders of an [`ErrorTemplate`]
+#[derive(Clone, Debug, Default)]
+pub struct ErrorContext {
+    pub method: Option<Method>,
+    pub path: Option<String>,
+    pub request_id: Option<String>,
+    /// Explanation of this particular error, see [`HttpErrorResponses::respond_with_detail`]
+    pub detail: Option<String>,
+}
+
+impl ErrorContext {
+    pub(crate) fn from_request(request: &Request) -> Self {
+        Self {
+            method: Some(request.method().clone()),
+            path: Some(request.uri().path().to_string()),
+            request_id: request
+                .extensions()
+                .get::<RequestContext>()
+                .map(|context| context.id().to_string()),
+            detail: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+pub(super) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Clone, Debug)]
+enum TemplateSource {
+    Inline,
+    File(PathBuf),
+    #[cfg(feature = "asset")]
+    Asset(bevy_asset::Handle<asset::ErrorTemplateAsset>),
+}
+
+#[derive(Debug, Default)]
+struct TemplateState {
+    html: String,
+    modified: Option<SystemTime>,
+}
+
+/// HTML error page with placeholders, replacing the built-in style.
+///
+/// `{{code}}`, `{{title}}`, `{{message}}`, `{{detail}}`, `{{method}}`, `{{path}}` and
+/// `{{request_id}}` are replaced with HTML-escaped values; unknown values become empty.
+/// Templates loaded from files or assets are reloaded when they change, in every clone.
+///
+/// ```rust,ignore
+/// let mut responses = HttpErrorResponses::default();
+/// responses.set_default_template(ErrorTemplate::from_file("assets/error.html")?);
+/// app.insert_resource(responses);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ErrorTemplate {
+    source: TemplateSource,
+    state: Arc<RwLock<TemplateState>>,
+}
+
+impl ErrorTemplate {
+    pub fn new(html: impl Into<String>) -> Self {
+        Self {
+            source: TemplateSource::Inline,
+            state: Arc::new(RwLock::new(TemplateState {
+                html: html.into(),
+                modified: None,
+            })),
+        }
+    }
+
+    /// Load a template from `path`, reloading it whenever the file changes
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let html = std::fs::read_to_string(&path)?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Ok(Self {
+            source: TemplateSource::File(path),
+            state: Arc::new(RwLock::new(TemplateState { html, modified })),
+        })
+    }
+
+    /// Use a template asset, see [`ErrorTemplateAssetPlugin`](crate::ErrorTemplateAssetPlugin).
+    /// The built-in pages are served until it has loaded.
+    #[cfg(feature = "asset")]
+    pub fn from_asset(handle: bevy_asset::Handle<asset::ErrorTemplateAsset>) -> Self {
+        Self {
+            source: TemplateSource::Asset(handle),
+            state: Default::default(),
+        }
+    }
+
+    /// Fill the placeholders, or `None` while the template has not loaded
+    pub fn render(
+        &self,
+        status: StatusCode,
+        message: &str,
+        context: &ErrorContext,
+    ) -> Option<String> {
+        let state = self.state.read().ok()?;
+        if state.html.is_empty() {
+            return None;
+        }
+
+        let code = status.as_u16().to_string();
+        let method = context.method.as_ref().map(Method::as_str);
+        let values = [
+            ("code", Some(code.as_str())),
+            ("title", status.canonical_reason()),
+            ("message", Some(message)),
+            ("detail", context.detail.as_deref()),
+            ("method", method),
+            ("path", context.path.as_deref()),
+            ("request_id", context.request_id.as_deref()),
+        ];
+
+        // A single pass, so placeholders inside the values are left alone
+        let mut html = String::with_capacity(state.html.len());
+        let mut rest = state.html.as_str();
+        while let Some(start) = rest.find("{{") {
+            html.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest[2..].find("}}").and_then(|end| {
+                let name = &rest[2..2 + end];
+                let (_, value) = values.iter().find(|(known, _)| *known == name)?;
+                Some((end + 4, value.unwrap_or_default()))
+            });
+            match value {
+                Some((len, value)) => {
+                    html.push_str(&escape_html(value));
+                    rest = &rest[len..];
+                }
+                None => {
+                    html.push_str("{{");
+                    rest = &rest[2..];
+                }
+            }
+        }
+        html.push_str(rest);
+        Some(html)
+    }
+
+    fn set_html(&self, html: String, modified: Option<SystemTime>) {
+        if let Ok(mut state) = self.state.write() {
+            state.html = html;
+            state.modified = modified;
+        }
+    }
+
+    /// Read the template file again if it changed since it was loaded
+    fn reload_if_changed(&self) {
+        let TemplateSource::File(path) = &self.source else {
+            return;
+        };
+        let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) else {
+            return;
+        };
+        let changed = self
+            .state
+            .read()
+            .is_ok_and(|state| state.modified != Some(modified));
+        if !changed {
+            return;
+        }
+
+        match std::fs::read_to_string(path) {
+            Ok(html) => {
+                bevy_log::debug!("Reloaded error template {}", path.display());
+                self.set_html(html, Some(modified));
+            }
+            Err(err) => bevy_log::warn!(
+                "Failed to reload error template {}: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+}
+
+/// Every set of error pages in use: the app's and the per-port ones
+fn all_error_responses<'a>(
+    responses: &'a Option<Res<HttpErrorResponses>>,
+    manager: &'a Option<Res<WebServerManager>>,
+) -> impl Iterator<Item = &'a HttpErrorResponses> {
+    let per_port = manager
+        .iter()
+        .flat_map(|manager| manager.iter())
+        .filter_map(|(_, server)| server.error_responses());
+    responses.as_deref().into_iter().chain(per_port)
+}
+
+/// Reload template files that changed on disk
+pub(crate) fn reload_error_templates(
+    responses: Option<Res<HttpErrorResponses>>,
+    manager: Option<Res<WebServerManager>>,
+    mut last_check: Local<Option<Instant>>,
+) {
+    if last_check.is_some_and(|last_check| last_check.elapsed() < RELOAD_INTERVAL) {
+        return;
+    }
+    *last_check = Some(Instant::now());
+
+    for responses in all_error_responses(&responses, &manager) {
+        for template in responses.templates() {
+            template.reload_if_changed();
+        }
+    }
+}
+
+#[cfg(feature = "asset")]
+pub(crate) mod asset {
+    use bevy_app::{App, Plugin, Update};
+    use bevy_asset::io::Reader;
+    use bevy_asset::{Asset, AssetApp, AssetEvent, AssetLoader, Assets, LoadContext};
+    use bevy_ecs::prelude::*;
+    use bevy_reflect::TypePath;
+
+    use super::{all_error_responses, TemplateSource};
+    use crate::{HttpErrorResponses, WebServerManager};
+
+    /// Error page template loaded from a `.error.html` file
+    #[derive(Asset, TypePath, Debug)]
+    pub struct ErrorTemplateAsset {
+        pub html: String,
+    }
+
+    #[derive(Default)]
+    struct ErrorTemplateLoader;
+
+    impl AssetLoader for ErrorTemplateLoader {
+        type Asset = ErrorTemplateAsset;
+        type Settings = ();
+        type Error = std::io::Error;
+
+        async fn load(
+            &self,
+            reader: &mut dyn Reader,
+            _settings: &(),
+            _load_context: &mut LoadContext<'_>,
+        ) -> Result<ErrorTemplateAsset, std::io::Error> {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let html = String::from_utf8(bytes)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            Ok(ErrorTemplateAsset { html })
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["error.html"]
+        }
+    }
+
+    /// Loads [`ErrorTemplateAsset`]s and keeps the [`ErrorTemplate`](super::ErrorTemplate)s
+    /// using them up to date, hot-reloading included. Add it after Bevy's `AssetPlugin`.
+    pub struct ErrorTemplateAssetPlugin;
+
+    impl Plugin for ErrorTemplateAssetPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_asset::<ErrorTemplateAsset>()
+                .init_asset_loader::<ErrorTemplateLoader>()
+                .add_systems(Update, sync_error_template_assets);
+        }
+    }
+
+    fn sync_error_template_assets(
+        mut events: EventReader<AssetEvent<ErrorTemplateAsset>>,
+        assets: Res<Assets<ErrorTemplateAsset>>,
+        responses: Option<Res<HttpErrorResponses>>,
+        manager: Option<Res<WebServerManager>>,
+    ) {
+        for event in events.read() {
+            let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+                continue;
+            };
+            let Some(asset) = assets.get(*id) else {
+                continue;
+            };
+
+            for responses in all_error_responses(&responses, &manager) {
+                for template in responses.templates() {
+                    if matches!(&template.source, TemplateSource::Asset(handle) if handle.id() == *id)
+                    {
+                        template.set_html(asset.html.clone(), None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_placeholders() {
+        let template = ErrorTemplate::new(
+            "<h1>{{code}} {{title}}</h1><p>{{message}}</p><p>{{detail}}</p><small>{{method}} {{path}} {{request_id}}</small>",
+        );
+        let context = ErrorContext {
+            method: Some(Method::GET),
+            path: Some("/players/<script>".to_string()),
+            request_id: Some("abc".to_string()),
+            detail: None,
+        };
+
+        let html = template
+            .render(StatusCode::NOT_FOUND, "Nothing here.", &context)
+            .unwrap();
+        assert_eq!(
+            html,
+            "<h1>404 Not Found</h1><p>Nothing here.</p><p></p><small>GET /players/&lt;script&gt; abc</small>"
+        );
+    }
+
+    #[test]
+    fn test_values_are_not_expanded() {
+        let template = ErrorTemplate::new("{{detail}} at {{path}} {{unknown}} {{code");
+        let context = ErrorContext {
+            method: None,
+            path: Some("/{{request_id}}".to_string()),
+            request_id: Some("abc".to_string()),
+            detail: Some("see {{path}}".to_string()),
+        };
+
+        let html = template
+            .render(StatusCode::NOT_FOUND, "", &context)
+            .unwrap();
+        assert_eq!(html, "see {{path}} at /{{request_id}} {{unknown}} {{code");
+    }
+
+    #[test]
+    fn test_file_template_reloads() {
+        let path = std::env::temp_dir().join(format!("webgate-error-{}.html", std::process::id()));
+        std::fs::write(&path, "old {{code}}").unwrap();
+        let template = ErrorTemplate::from_file(&path).unwrap();
+        let clone = template.clone();
+
+        std::fs::write(&path, "new {{code}}").unwrap();
+        // Make sure the modification time differs even on coarse file systems
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        template.reload_if_changed();
+
+        let html = clone.render(StatusCode::GONE, "", &ErrorContext::default());
+        assert_eq!(html.as_deref(), Some("new 410"));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,292 @@
+use axum::{
+    extract::{FromRequestParts, RawPathParams},
+    http::{request::Parts, StatusCode},
+    response::Response,
+};
+use bevy_defer::{AsyncAccess, AsyncWorld};
+use bevy_derive::{Deref, DerefMut};
+use bevy_ecs::{
+    prelude::*,
+    query::{Has, QueryFilter, QueryItem, ReadOnlyQueryData},
+};
+use bevy_log::{debug, error};
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+use crate::HttpErrorResponses;
+
+/// Extracts a clone of resource `T`.
+///
+/// Responds with a 500 page from [`HttpErrorResponses`] if the resource does not exist.
+///
+/// ```rust,ignore
+/// async fn settings(WebRes(settings): WebRes<GameSettings>) -> String {
+///     format!("difficulty: {}", settings.difficulty)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, Deref, DerefMut)]
+pub struct WebRes<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for WebRes<T>
+where
+    T: Resource + Clone,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        AsyncWorld
+            .resource::<T>()
+            .get(|resource| WebRes(resource.clone()))
+            .map_err(|err| {
+                error!(
+                    "Failed to extract resource {}: {}",
+                    std::any::type_name::<T>(),
+                    err
+                );
+                HttpErrorResponses::respond(StatusCode::INTERNAL_SERVER_ERROR)
+            })
+    }
+}
+
+/// Query data that can be copied out of the world into a [`WebQuery`] snapshot.
+///
+/// Implemented for `Entity`, `&T` where `T: Component + Clone`, `Has<T>`, `Option<Q>`
+/// and tuples of up to eight of them.
+pub trait WebQueryData {
+    /// Query fetching the data from the world
+    type Data: ReadOnlyQueryData + 'static;
+    /// Owned copy handed to the handler
+    type Item: Send + 'static;
+
+    fn snapshot(item: QueryItem<'_, Self::Data>) -> Self::Item;
+}
+
+impl WebQueryData for Entity {
+    type Data = Entity;
+    type Item = Entity;
+
+    fn snapshot(item: Entity) -> Entity {
+        item
+    }
+}
+
+impl<T: Component + Clone> WebQueryData for &T {
+    type Data = &'static T;
+    type Item = T;
+
+    fn snapshot(item: &T) -> T {
+        item.clone()
+    }
+}
+
+impl<T: Component> WebQueryData for Has<T> {
+    type Data = Has<T>;
+    type Item = bool;
+
+    fn snapshot(item: bool) -> bool {
+        item
+    }
+}
+
+impl<Q: WebQueryData> WebQueryData for Option<Q> {
+    type Data = Option<Q::Data>;
+    type Item = Option<Q::Item>;
+
+    fn snapshot(item: QueryItem<'_, Self::Data>) -> Self::Item {
+        item.map(Q::snapshot)
+    }
+}
+
+macro_rules! impl_web_query_data_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: WebQueryData),*> WebQueryData for ($($name,)*) {
+            type Data = ($($name::Data,)*);
+            type Item = ($($name::Item,)*);
+
+            #[allow(non_snake_case)]
+            fn snapshot(($($name,)*): QueryItem<'_, Self::Data>) -> Self::Item {
+                ($($name::snapshot($name),)*)
+            }
+        }
+    };
+}
+
+impl_web_query_data_tuple!(A);
+impl_web_query_data_tuple!(A, B);
+impl_web_query_data_tuple!(A, B, C);
+impl_web_query_data_tuple!(A, B, C, D);
+impl_web_query_data_tuple!(A, B, C, D, E);
+impl_web_query_data_tuple!(A, B, C, D, E, F);
+impl_web_query_data_tuple!(A, B, C, D, E, F, G);
+impl_web_query_data_tuple!(A, B, C, D, E, F, G, H);
+
+/// Extracts a snapshot of every entity matching query `Q` and filter `F`.
+///
+/// ```rust,ignore
+/// async fn scoreboard(players: WebQuery<(&Player, &Score), With<Active>>) -> String {
+///     players
+///         .iter()
+///         .map(|(player, score)| format!("{}: {}\n", player.0, score.0))
+///         .collect()
+/// }
+/// ```
+#[derive(Deref, DerefMut)]
+pub struct WebQuery<Q: WebQueryData, F: QueryFilter = ()> {
+    #[deref]
+    pub items: Vec<Q::Item>,
+    marker: PhantomData<fn() -> F>,
+}
+
+impl<Q: WebQueryData, F: QueryFilter> WebQuery<Q, F> {
+    pub fn into_inner(self) -> Vec<Q::Item> {
+        self.items
+    }
+}
+
+impl<Q: WebQueryData, F: QueryFilter> IntoIterator for WebQuery<Q, F> {
+    type Item = Q::Item;
+    type IntoIter = std::vec::IntoIter<Q::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<Q, F, S> FromRequestParts<S> for WebQuery<Q, F>
+where
+    Q: WebQueryData,
+    F: QueryFilter + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let items = AsyncWorld.run(|world| {
+            let mut query = world.query_filtered::<Q::Data, F>();
+            query.iter(world).map(Q::snapshot).collect()
+        });
+
+        Ok(WebQuery {
+            items,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Extracts an existing entity from the `{entity}` path parameter.
+///
+/// Accepts both the `Display` form of an entity (`12v1`) and [`Entity::to_bits`].
+/// Malformed ids get a 400 page and entities that do not exist a 404 page from
+/// [`HttpErrorResponses`]. Routes without an `{entity}` parameter are a mistake in the
+/// app and get a 500 page.
+#[derive(Clone, Copy, Debug, Deref, Eq, Hash, PartialEq)]
+pub struct WebEntity(pub Entity);
+
+impl<S: Send + Sync> FromRequestParts<S> for WebEntity {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(|err| {
+                debug!("Missing entity path parameter: {}", err);
+                HttpErrorResponses::respond(StatusCode::BAD_REQUEST)
+            })?;
+
+        let Some((_, value)) = params.iter().find(|(key, _)| *key == "entity") else {
+            error!(
+                "WebEntity used on {} without an {{entity}} path parameter",
+                parts.uri.path()
+            );
+            return Err(HttpErrorResponses::respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        };
+
+        let Some(entity) = parse_entity(value) else {
+            return Err(HttpErrorResponses::respond(StatusCode::BAD_REQUEST));
+        };
+
+        if AsyncWorld.run(|world| world.get_entity(entity).is_ok()) {
+            Ok(WebEntity(entity))
+        } else {
+            Err(HttpErrorResponses::respond(StatusCode::NOT_FOUND))
+        }
+    }
+}
+
+/// Parse `<index>v<generation>` or the bits of an entity
+fn parse_entity(value: &str) -> Option<Entity> {
+    let bits = match value.split_once('v') {
+        Some((index, generation)) => {
+            let index: u32 = index.parse().ok()?;
+            let generation: u32 = generation.parse().ok()?;
+            (u64::from(generation) << 32) | u64::from(index)
+        }
+        None => value.parse().ok()?,
+    };
+    Entity::try_from_bits(bits).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entity() {
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+
+        assert_eq!(parse_entity(&entity.to_string()), Some(entity));
+        assert_eq!(parse_entity(&entity.to_bits().to_string()), Some(entity));
+        assert_eq!(parse_entity("3v0"), None);
+        assert_eq!(parse_entity("abc"), None);
+    }
+
+    #[test]
+    fn test_entity_param_is_required() {
+        use axum::{body::Body, extract::Request, routing::get, Router};
+        use tower::ServiceExt;
+
+        let handler = get(|WebEntity(entity): WebEntity| async move { entity.to_string() });
+        let router = Router::new()
+            .route("/players/{id}", handler.clone())
+            .route("/teams/{team}/players/{entity}", handler);
+        let status = |path: &str| {
+            let request = Request::get(path).body(Body::empty()).unwrap();
+            async_io::block_on(router.clone().oneshot(request))
+                .unwrap()
+                .status()
+        };
+
+        // A differently named parameter isn't taken for the entity
+        assert_eq!(status("/players/12v1"), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(status("/teams/12v1/players/abc"), StatusCode::BAD_REQUEST);
+    }
+
+    #[derive(Clone, Component, Debug, PartialEq)]
+    struct Score(u32);
+
+    #[derive(Component)]
+    struct Active;
+
+    #[test]
+    fn test_snapshot_query_data() {
+        let mut world = World::new();
+        let first = world.spawn((Score(1), Active)).id();
+        let second = world.spawn(Score(2)).id();
+
+        let mut query = world.query::<<(Entity, &Score, Has<Active>) as WebQueryData>::Data>();
+        let mut items: Vec<_> = query
+            .iter(&world)
+            .map(<(Entity, &Score, Has<Active>)>::snapshot)
+            .collect();
+        items.sort_by_key(|(_, score, _)| score.0);
+
+        assert_eq!(
+            items,
+            vec![(first, Score(1), true), (second, Score(2), false)]
+        );
+    }
+}
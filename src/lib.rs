@@ -2,10 +2,26 @@ use bevy_app::{App, Plugin, Startup, Update};
 use bevy_defer::AsyncPlugin;
 use std::net::{IpAddr, Ipv4Addr};
 
+// Lets `embed_dir!`, which names `::bevy_webgate`, expand inside this crate
+extern crate self as bevy_webgate;
+
+mod access_log;
 mod app_ext;
+mod blocking;
+mod catch_panic;
+mod component_stream;
+mod compression;
+mod deterministic;
 mod error;
+mod extract;
+mod metrics;
+mod request_context;
 mod server;
+mod sse;
 mod static_assets;
+mod system_handler;
+mod waker;
+mod websocket;
 
 pub mod prelude;
 pub mod utils;
@@ -16,10 +32,28 @@ mod tests;
 pub const DEFAULT_PORT: WebPort = 8080;
 pub const DEFAULT_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
 
+pub use access_log::{AccessLogConfig, AccessLogFormat, AccessLogTarget};
 pub use app_ext::*;
+pub use bevy_webgate_macros::embed_dir;
+pub use blocking::{blocking, compute_with_world, spawn_async_compute, spawn_compute};
+#[cfg(feature = "compression")]
+pub use compression::CompressionConfig;
 pub use error::*;
-pub use server::{WebPort, WebServer, WebServerConfig, WebServerManager};
+pub use extract::{WebEntity, WebQuery, WebQueryData, WebRes};
+pub use metrics::*;
+pub use request_context::{RequestContext, RequestId, REQUEST_ID_HEADER};
+pub use server::{
+    IoMode, ServerStats, ServerStatsSnapshot, ServerStatus, WebPort, WebServer, WebServerConfig,
+    WebServerManager, LATENCY_BUCKETS_MS,
+};
+pub use sse::SseConfig;
 pub use static_assets::*;
+pub use system_handler::WebRequest;
+pub use waker::WebServerWaker;
+pub use websocket::{
+    WebSocketClosed, WebSocketConfig, WebSocketConnection, WebSocketMessage, WebSocketOpened,
+    WebSocketPlugin, WebSocketReceived, WebSocketSend,
+};
 
 pub struct BevyWebServerPlugin;
 
@@ -29,7 +63,7 @@ impl Plugin for BevyWebServerPlugin {
             app.add_plugins(AsyncPlugin::default_settings());
         }
 
-        app.add_plugins(WebStaticAssetsPlugin);
+        app.add_plugins((WebStaticAssetsPlugin, WebSocketPlugin));
 
         let world = app.world_mut();
 
@@ -40,6 +74,9 @@ impl Plugin for BevyWebServerPlugin {
             world.insert_resource(legacy_config);
         }
 
+        #[cfg(feature = "winit")]
+        app.add_systems(bevy_app::PreStartup, waker::install_winit_waker);
+
         app.add_systems(Startup, WebServerManager::changed)
             .add_systems(
                 Update,
@@ -0,0 +1,300 @@
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use bevy_defer::AsyncWorld;
+use bevy_diagnostic::DiagnosticsStore;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::{ServerStatsSnapshot, WebPort, WebServerManager, LATENCY_BUCKETS_MS};
+
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Handler rendering the statistics of every server managed by [`WebServerManager`]
+/// and all enabled [`DiagnosticsStore`] entries in OpenMetrics text format.
+///
+/// Mount it with [`WebServerAppExt::port_metrics`](crate::WebServerAppExt::port_metrics)
+/// or as a regular route, typically on an admin-only port.
+pub async fn openmetrics_handler() -> Response {
+    let (servers, diagnostics) = AsyncWorld.run(|world| {
+        let mut servers: Vec<_> = world
+            .get_resource::<WebServerManager>()
+            .map(|manager| {
+                manager
+                    .iter()
+                    .map(|(port, server)| (*port, server.stats().snapshot()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        servers.sort_by_key(|(port, _)| *port);
+
+        let diagnostics: Vec<_> = world
+            .get_resource::<DiagnosticsStore>()
+            .map(|store| {
+                store
+                    .iter()
+                    .filter(|diagnostic| diagnostic.is_enabled)
+                    .filter_map(|diagnostic| {
+                        let value = diagnostic.smoothed().or_else(|| diagnostic.value())?;
+                        Some((diagnostic.path().as_str().to_string(), value))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        (servers, diagnostics)
+    });
+
+    (
+        [(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+        render_openmetrics(&servers, &diagnostics),
+    )
+        .into_response()
+}
+
+pub(crate) fn render_openmetrics(
+    servers: &[(WebPort, ServerStatsSnapshot)],
+    diagnostics: &[(String, f64)],
+) -> String {
+    let mut out = String::new();
+
+    metric_header(
+        &mut out,
+        "webgate_requests",
+        "counter",
+        "HTTP requests received.",
+    );
+    for (port, stats) in servers {
+        let _ = writeln!(
+            out,
+            "webgate_requests_total{{port=\"{port}\"}} {}",
+            stats.total_requests
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "webgate_requests_in_flight",
+        "gauge",
+        "HTTP requests currently being handled.",
+    );
+    for (port, stats) in servers {
+        let _ = writeln!(
+            out,
+            "webgate_requests_in_flight{{port=\"{port}\"}} {}",
+            stats.in_flight
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "webgate_responses",
+        "counter",
+        "HTTP responses sent, by status class.",
+    );
+    for (port, stats) in servers {
+        for (index, count) in stats.responses_by_class.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "webgate_responses_total{{port=\"{port}\",class=\"{}xx\"}} {count}",
+                index + 1
+            );
+        }
+    }
+
+    metric_header(
+        &mut out,
+        "webgate_received_bytes",
+        "counter",
+        "Request body bytes received.",
+    );
+    for (port, stats) in servers {
+        let _ = writeln!(
+            out,
+            "webgate_received_bytes_total{{port=\"{port}\"}} {}",
+            stats.bytes_in
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "webgate_sent_bytes",
+        "counter",
+        "Response body bytes sent.",
+    );
+    for (port, stats) in servers {
+        let _ = writeln!(
+            out,
+            "webgate_sent_bytes_total{{port=\"{port}\"}} {}",
+            stats.bytes_out
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "webgate_request_duration_seconds",
+        "histogram",
+        "Time until the response headers were ready.",
+    );
+    for (port, stats) in servers {
+        let mut cumulative = 0;
+        for (upper_ms, count) in LATENCY_BUCKETS_MS.iter().zip(stats.latency_buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "webgate_request_duration_seconds_bucket{{port=\"{port}\",le=\"{}\"}} {cumulative}",
+                format_float(*upper_ms as f64 / 1000.0)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "webgate_request_duration_seconds_bucket{{port=\"{port}\",le=\"+Inf\"}} {}",
+            stats.latency_count()
+        );
+        let _ = writeln!(
+            out,
+            "webgate_request_duration_seconds_sum{{port=\"{port}\"}} {}",
+            format_float(stats.latency_sum.as_secs_f64())
+        );
+        let _ = writeln!(
+            out,
+            "webgate_request_duration_seconds_count{{port=\"{port}\"}} {}",
+            stats.latency_count()
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "webgate_handler_panics",
+        "counter",
+        "Handlers that panicked and were answered with a 500.",
+    );
+    for (port, stats) in servers {
+        let _ = writeln!(
+            out,
+            "webgate_handler_panics_total{{port=\"{port}\"}} {}",
+            stats.handler_panics
+        );
+    }
+
+    // Distinct paths such as `a/b` and `a_b` can sanitise to the same name
+    let mut names = HashSet::new();
+    for (path, value) in diagnostics {
+        let base = diagnostic_metric_name(path);
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while !names.insert(name.clone()) {
+            suffix += 1;
+            name = format!("{base}_{suffix}");
+        }
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(out, "# HELP {name} Bevy diagnostic '{path}'.");
+        let _ = writeln!(out, "{name} {}", format_float(*value));
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "# HELP {name} {help}");
+}
+
+/// Turn a diagnostic path such as `fps` or `my_game/enemies` into a valid metric name
+fn diagnostic_metric_name(path: &str) -> String {
+    let sanitized: String = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("bevy_{}", sanitized.to_ascii_lowercase())
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render_server_stats() {
+        let mut stats = ServerStatsSnapshot {
+            total_requests: 3,
+            responses_by_class: [0, 2, 0, 1, 0],
+            bytes_out: 42,
+            latency_sum: Duration::from_millis(30),
+            ..Default::default()
+        };
+        stats.latency_buckets[0] = 2;
+        stats.latency_buckets[3] = 1;
+
+        let text = render_openmetrics(&[(8080, stats)], &[]);
+
+        assert!(text.contains("# TYPE webgate_requests counter\n"));
+        assert!(text.contains("webgate_requests_total{port=\"8080\"} 3\n"));
+        assert!(text.contains("webgate_responses_total{port=\"8080\",class=\"4xx\"} 1\n"));
+        assert!(text.contains("webgate_sent_bytes_total{port=\"8080\"} 42\n"));
+        assert!(text
+            .contains("webgate_request_duration_seconds_bucket{port=\"8080\",le=\"0.001\"} 2\n"));
+        assert!(text
+            .contains("webgate_request_duration_seconds_bucket{port=\"8080\",le=\"0.025\"} 3\n"));
+        assert!(
+            text.contains("webgate_request_duration_seconds_bucket{port=\"8080\",le=\"+Inf\"} 3\n")
+        );
+        assert!(text.contains("webgate_request_duration_seconds_sum{port=\"8080\"} 0.03\n"));
+        assert!(text.contains("webgate_handler_panics_total{port=\"8080\"} 0\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_render_diagnostics() {
+        let text = render_openmetrics(
+            &[],
+            &[
+                ("fps".to_string(), 59.5),
+                ("my_game/Enemy Count".to_string(), 12.0),
+            ],
+        );
+
+        assert!(text.contains("# TYPE bevy_fps gauge\n"));
+        assert!(text.contains("bevy_fps 59.5\n"));
+        assert!(text.contains("bevy_my_game_enemy_count 12\n"));
+    }
+
+    #[test]
+    fn test_colliding_diagnostic_names() {
+        let text = render_openmetrics(
+            &[],
+            &[
+                ("a/b".to_string(), 1.0),
+                ("a_b".to_string(), 2.0),
+                ("a b".to_string(), 3.0),
+                ("a_b_2".to_string(), 4.0),
+            ],
+        );
+
+        assert!(text.contains("# HELP bevy_a_b Bevy diagnostic 'a/b'.\nbevy_a_b 1\n"));
+        assert!(text.contains("# HELP bevy_a_b_2 Bevy diagnostic 'a_b'.\nbevy_a_b_2 2\n"));
+        assert!(text.contains("# HELP bevy_a_b_3 Bevy diagnostic 'a b'.\nbevy_a_b_3 3\n"));
+        assert!(text.contains("# HELP bevy_a_b_2_2 Bevy diagnostic 'a_b_2'.\nbevy_a_b_2_2 4\n"));
+        assert_eq!(text.matches("# TYPE bevy_a_b gauge").count(), 1);
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(f64::INFINITY), "+Inf");
+        assert_eq!(format_float(f64::NAN), "NaN");
+        assert_eq!(format_float(0.25), "0.25");
+    }
+}
@@ -1,4 +1,24 @@
 pub use crate::app_ext::*;
-pub use crate::error::{HttpErrorResponses, WebServerError, WebServerResult};
-pub use crate::server::{ServerStatus, WebPort, WebServer, WebServerConfig, WebServerManager};
-pub use crate::{BevyWebServerPlugin, DEFAULT_IP, DEFAULT_PORT};
+pub use crate::blocking::{blocking, compute_with_world, spawn_async_compute, spawn_compute};
+#[cfg(feature = "compression")]
+pub use crate::compression::CompressionConfig;
+pub use crate::embed_dir;
+pub use crate::error::{
+    ErrorContext, ErrorFormat, ErrorTemplate, HttpErrorResponses, ProblemDetails, WebServerError,
+    WebServerResult,
+};
+#[cfg(feature = "asset")]
+pub use crate::error::{ErrorTemplateAsset, ErrorTemplateAssetPlugin};
+pub use crate::extract::{WebEntity, WebQuery, WebQueryData, WebRes};
+pub use crate::server::{
+    IoMode, ServerStats, ServerStatsSnapshot, ServerStatus, WebPort, WebServer, WebServerConfig,
+    WebServerManager,
+};
+pub use crate::static_assets::{EmbeddedDir, StaticDir, SymlinkPolicy};
+pub use crate::system_handler::WebRequest;
+pub use crate::waker::WebServerWaker;
+pub use crate::websocket::{
+    WebSocketClosed, WebSocketConfig, WebSocketConnection, WebSocketMessage, WebSocketOpened,
+    WebSocketReceived, WebSocketSend,
+};
+pub use crate::{BevyWebServerPlugin, SseConfig, DEFAULT_IP, DEFAULT_PORT};
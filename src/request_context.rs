@@ -0,0 +1,301 @@
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderName, HeaderValue},
+    response::Response,
+};
+use bevy_defer::{AsyncAccess, AsyncWorld};
+use bevy_diagnostic::FrameCount;
+use bevy_ecs::world::World;
+use bevy_log::{
+    info_span,
+    tracing::{field, Instrument, Span},
+};
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::hash::{BuildHasher, Hasher};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Header used to propagate request ids from clients and proxies, and returned on every response
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming request id that is propagated instead of replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Identifier correlating a request with its logs
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    /// Generate a new id that is unique within this process and unlikely to collide across processes
+    pub fn generate() -> Self {
+        static PREFIX: OnceLock<u64> = OnceLock::new();
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let prefix = *PREFIX.get_or_init(|| RandomState::new().build_hasher().finish());
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(format!("{:016x}{:08x}", prefix, count).into())
+    }
+
+    /// Accept an id supplied by a client, if it is short and made of visible ASCII only
+    pub fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value.bytes().all(|byte| byte.is_ascii_graphic());
+        valid.then(|| Self(value.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Request id and tracing span of the request being handled.
+///
+/// Extract it in a handler to correlate world access with the originating request:
+///
+/// ```rust,ignore
+/// async fn handler(context: RequestContext) -> String {
+///     let count = context.run(|world| world.entities().len());
+///     format!("{count} entities (request {})", context.id())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    id: RequestId,
+    span: Span,
+    /// Last frame the request was handled in, [`NO_FRAME`] before that
+    frame: Arc<AtomicU64>,
+}
+
+/// Frame of requests that haven't been handled on the app's executor yet
+const NO_FRAME: u64 = u64::MAX;
+
+impl RequestContext {
+    fn new(id: RequestId, method: &str, path: &str) -> Self {
+        let span = info_span!(
+            "request",
+            request_id = %id,
+            method = %method,
+            path = %path,
+            frame = field::Empty,
+        );
+        Self {
+            id,
+            span,
+            frame: Arc::new(AtomicU64::new(NO_FRAME)),
+        }
+    }
+
+    pub fn id(&self) -> &RequestId {
+        &self.id
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Bevy frame the request was last handled in, and so could touch the world in
+    pub fn frame(&self) -> Option<u32> {
+        match self.frame.load(Ordering::Relaxed) {
+            NO_FRAME => None,
+            frame => Some(frame as u32),
+        }
+    }
+
+    fn record_frame(&self, frame: u32) {
+        if self.frame.swap(frame.into(), Ordering::Relaxed) != u64::from(frame) {
+            self.span.record("frame", frame);
+        }
+    }
+
+    /// Record the current frame, if running on the app's executor
+    fn record_current_frame(&self) {
+        if !bevy_defer::in_async_context() {
+            return;
+        }
+        if let Ok(frame) = AsyncWorld.resource::<FrameCount>().get(|frame| frame.0) {
+            self.record_frame(frame);
+        }
+    }
+
+    /// Run a closure with exclusive world access inside the request span,
+    /// recording the Bevy frame number the request touched the world in.
+    pub fn run<T>(&self, f: impl FnOnce(&mut World) -> T) -> T {
+        AsyncWorld.run(|world| {
+            let _entered = self.span.enter();
+            if let Some(frame) = world.get_resource::<FrameCount>() {
+                self.record_frame(frame.0);
+            }
+            f(world)
+        })
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<RequestContext>()
+            .cloned()
+            .unwrap_or_else(|| {
+                RequestContext::new(
+                    RequestId::generate(),
+                    parts.method.as_str(),
+                    parts.uri.path(),
+                )
+            }))
+    }
+}
+
+/// Tower layer assigning a [`RequestId`] and tracing span to every request
+#[derive(Clone, Default)]
+pub(crate) struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for RequestIdService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(RequestId::generate);
+        let context = RequestContext::new(id, request.method().as_str(), request.uri().path());
+        request.extensions_mut().insert(context.clone());
+
+        let span = context.span.clone();
+        let future = span.in_scope(|| self.inner.call(request));
+
+        Box::pin(
+            async move {
+                // Handlers are polled in the frames they can touch the world in, whether they
+                // use `RequestContext::run`, extractors, system handlers or `AsyncWorld` directly
+                let mut future = pin!(future);
+                let mut response = poll_fn(|cx| {
+                    context.record_current_frame();
+                    future.as_mut().poll(cx)
+                })
+                .await?;
+                if let Ok(value) = HeaderValue::from_str(context.id.as_str()) {
+                    response
+                        .headers_mut()
+                        .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                }
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{WebServerAppExt, WebServerManager};
+    use axum::{body::Body, routing::get};
+    use bevy_app::App;
+    use bevy_defer::{systems::run_async_executor, AsyncExecutor, AsyncPlugin};
+    use bevy_ecs::resource::Resource;
+    use tower::ServiceExt;
+
+    #[derive(Default, Resource)]
+    struct Handled(Option<RequestContext>);
+
+    #[test]
+    fn test_generated_ids_are_unique() {
+        let first = RequestId::generate();
+        let second = RequestId::generate();
+        assert_ne!(first, second);
+        assert_eq!(first.as_str().len(), 24);
+        assert!(RequestId::from_header(&HeaderValue::from_str(first.as_str()).unwrap()).is_some());
+    }
+
+    #[test]
+    fn test_incoming_id_validation() {
+        let accepted = HeaderValue::from_static("abc-123_XYZ");
+        assert_eq!(
+            RequestId::from_header(&accepted).unwrap().as_str(),
+            "abc-123_XYZ"
+        );
+
+        assert!(RequestId::from_header(&HeaderValue::from_static("")).is_none());
+        assert!(RequestId::from_header(&HeaderValue::from_static("has space")).is_none());
+        let too_long = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        assert!(RequestId::from_header(&HeaderValue::from_str(&too_long).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_frame_is_recorded_without_run() {
+        let mut app = App::new();
+        app.add_plugins((bevy::MinimalPlugins, AsyncPlugin::default_settings()))
+            .init_resource::<Handled>()
+            .port_route(
+                29190,
+                "/",
+                get(|context: RequestContext| async move {
+                    // Plain world access, not going through `RequestContext::run`
+                    AsyncWorld
+                        .resource::<Handled>()
+                        .get_mut(|handled| handled.0 = Some(context))
+                        .unwrap();
+                }),
+            );
+        app.update();
+        app.update();
+        let frame = app.world().resource::<FrameCount>().0;
+        assert_ne!(frame, 0);
+
+        let router = app
+            .world()
+            .resource::<WebServerManager>()
+            .get_server(&29190)
+            .unwrap()
+            .service_router();
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        app.world()
+            .non_send_resource::<AsyncExecutor>()
+            .spawn_any(router.oneshot(request));
+        run_async_executor(app.world_mut());
+
+        let context = app.world().resource::<Handled>().0.clone().unwrap();
+        assert_eq!(context.frame(), Some(frame));
+    }
+}
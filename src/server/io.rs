@@ -0,0 +1,299 @@
+use async_executor::Executor;
+use async_io::{Async, Timer};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::Router;
+use bevy_defer::{AccessError, AsyncAccess, AsyncExecutor, AsyncWorld};
+use bevy_log::{debug, error, info};
+use futures_util::future::{select, Either};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use smol_hyper::rt::{FuturesIo, SmolTimer};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::pin::pin;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tower::ServiceExt;
+
+use super::{ConnectionGuard, ConnectionTracker, TaskType, WebPort, WebServer, WebServerManager};
+use crate::waker::wake_app_on_progress;
+use crate::{ErrorContext, ErrorFormat, HttpErrorResponses, WebServerError, WebServerResult};
+
+/// Where a server drives its network I/O
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IoMode {
+    /// On the app's async executor, so sockets are only read and written while the app updates
+    #[default]
+    Main,
+    /// On `threads` dedicated threads. Requests are handed to the app's async executor,
+    /// so handlers keep their world access, while accepting, parsing and writing
+    /// responses carry on between frames and during slow handlers.
+    Dedicated { threads: usize },
+}
+
+/// Request handed from the I/O threads to the app, with the channel its response goes back on
+type Dispatch = (Request, async_channel::Sender<Response>);
+
+/// Threads running a server's connections; they exit and cancel their tasks once dropped
+#[derive(Debug)]
+pub(crate) struct IoPool {
+    executor: Arc<Executor<'static>>,
+    _shutdown: async_channel::Sender<()>,
+}
+
+impl IoPool {
+    pub(crate) fn new(port: WebPort, threads: usize) -> std::io::Result<Self> {
+        let executor = Arc::new(Executor::new());
+        let (shutdown, stopped) = async_channel::bounded::<()>(1);
+
+        for index in 0..threads.max(1) {
+            let executor = executor.clone();
+            let stopped = stopped.clone();
+            std::thread::Builder::new()
+                .name(format!("webserver-io-{}-{}", port, index))
+                .spawn(move || async_io::block_on(executor.run(stopped.recv())))?;
+        }
+
+        Ok(Self {
+            executor,
+            _shutdown: shutdown,
+        })
+    }
+
+    pub(crate) fn spawn<T: Send + 'static>(
+        &self,
+        future: impl std::future::Future<Output = T> + Send + 'static,
+    ) -> async_executor::Task<T> {
+        self.executor.spawn(future)
+    }
+}
+
+/// Request extension naming the I/O threads a request arrived on, so long-lived work
+/// such as WebSockets can keep its socket I/O off the app's executor
+#[derive(Clone, Debug)]
+pub(crate) struct IoExecutor(Weak<Executor<'static>>);
+
+impl IoExecutor {
+    /// Run `future` on the I/O threads, or `None` if they have shut down.
+    /// The task resolves to `None` if it is cancelled by the threads shutting down.
+    pub(crate) fn spawn<T: Send + 'static>(
+        &self,
+        future: impl std::future::Future<Output = T> + Send + 'static,
+    ) -> Option<async_executor::FallibleTask<T>> {
+        let executor = self.0.upgrade()?;
+        Some(executor.spawn(future).fallible())
+    }
+}
+
+impl WebServer {
+    /// Accept loop of [`IoMode::Dedicated`] servers, dispatching their requests to the app
+    pub(super) async fn listen_dedicated(
+        ip: IpAddr,
+        port: WebPort,
+        router: Router,
+        threads: usize,
+    ) -> WebServerResult<()> {
+        let async_executor = AsyncWorld
+            .non_send_resource::<AsyncExecutor>()
+            .get(|executor| executor.clone())?;
+        let (tracker, server_errors) =
+            AsyncWorld.resource::<WebServerManager>().get(|manager| {
+                manager
+                    .get_server(&port)
+                    .map(|server| {
+                        (
+                            server.connection_tracker.clone(),
+                            server.error_responses().cloned(),
+                        )
+                    })
+                    .ok_or(AccessError::Custom("No server found on port"))
+            })??;
+        // Error pages can't be rendered from the world on the I/O threads
+        let errors = match server_errors {
+            Some(errors) => errors,
+            None => AsyncWorld
+                .resource::<HttpErrorResponses>()
+                .get(|errors| errors.clone())
+                .unwrap_or_default(),
+        };
+
+        let listener = Self::bind(ip, port).await?;
+        let pool = IoPool::new(port, threads)
+            .map_err(|e| WebServerError::io_error("spawn I/O threads", e))?;
+        let (dispatch, requests) = async_channel::unbounded::<Dispatch>();
+        // Closed on shutdown, telling open connections to finish their request and close
+        let (drain, draining) = async_channel::bounded::<()>(1);
+        let mut request_id = 0;
+
+        let mut accept = Some(pool.spawn(accept_connections(
+            listener,
+            Arc::downgrade(&pool.executor),
+            tracker.clone(),
+            dispatch,
+            errors,
+            draining,
+        )));
+        info!(
+            "Web server listening on {}:{} with {} I/O threads",
+            ip,
+            port,
+            threads.max(1)
+        );
+
+        loop {
+            if accept.is_some()
+                && AsyncWorld
+                    .resource::<WebServerManager>()
+                    .get(|manager| manager.shutdown_requested(&port))?
+            {
+                info!(
+                    "Shutdown requested for server on port {}, stopping accept loop",
+                    port
+                );
+                // Dropping the task closes the listener, open connections finish their
+                // current request instead of waiting for another one
+                accept = None;
+                drain.close();
+            }
+            // The pool goes away with this task, so wait for the open connections first
+            if accept.is_none() && tracker.active_connections() == 0 {
+                return Ok(());
+            }
+
+            // Wake up now and then to notice a shutdown request
+            let request = pin!(requests.recv());
+            let tick = pin!(AsyncWorld.sleep(Duration::from_millis(Self::ERROR_SLEEP_INTERVAL_MS)));
+            let next = select(request, tick).await;
+            if let Either::Left((Ok((request, responder)), _)) = next {
+                let router = router.clone();
+                let task_type = TaskType::Request(request_id);
+                request_id += 1;
+                let handler_task = async_executor.spawn_task(wake_app_on_progress(async move {
+                    let Ok(response) = router.oneshot(request).await;
+                    // The connection may have closed in the meantime
+                    let _ = responder.try_send(response);
+
+                    AsyncWorld
+                        .resource::<WebServerManager>()
+                        .get_mut(|manager| {
+                            if let Some(server) = manager.get_server_mut(&port) {
+                                server.task_store_mut().remove(&task_type);
+                            }
+                        })?;
+                    Ok(())
+                }));
+
+                // Stored with the server, so stopping it cancels the handlers in flight
+                AsyncWorld
+                    .resource::<WebServerManager>()
+                    .get_mut(|manager| {
+                        let Some(server) = manager.get_server_mut(&port) else {
+                            return Err(AccessError::Custom("No server found on port"));
+                        };
+                        server.task_store_mut().insert(task_type, handler_task);
+                        Ok(())
+                    })??;
+            }
+        }
+    }
+}
+
+async fn accept_connections(
+    listener: Async<TcpListener>,
+    executor: Weak<Executor<'static>>,
+    tracker: ConnectionTracker,
+    dispatch: async_channel::Sender<Dispatch>,
+    errors: HttpErrorResponses,
+    draining: async_channel::Receiver<()>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((client, peer_addr)) => {
+                // A strong reference held here would keep the pool's executor alive forever
+                let Some(executor) = executor.upgrade() else {
+                    return;
+                };
+                let io = IoExecutor(Arc::downgrade(&executor));
+                executor
+                    .spawn(serve_connection(
+                        client,
+                        peer_addr,
+                        io,
+                        tracker.new_connection(),
+                        dispatch.clone(),
+                        errors.clone(),
+                        draining.clone(),
+                    ))
+                    .detach();
+            }
+            Err(e) => {
+                error!("Error accepting connection: {}", e);
+                Timer::after(Duration::from_millis(WebServer::ERROR_SLEEP_INTERVAL_MS)).await;
+            }
+        }
+    }
+}
+
+async fn serve_connection(
+    client: Async<TcpStream>,
+    peer_addr: SocketAddr,
+    io: IoExecutor,
+    _guard: ConnectionGuard,
+    dispatch: async_channel::Sender<Dispatch>,
+    errors: HttpErrorResponses,
+    draining: async_channel::Receiver<()>,
+) {
+    let service = service_fn(move |mut request: hyper::Request<hyper::body::Incoming>| {
+        let dispatch = dispatch.clone();
+        let errors = errors.clone();
+        let io = io.clone();
+        async move {
+            // Expose the peer address to handlers and layers through `ConnectInfo`
+            request.extensions_mut().insert(ConnectInfo(peer_addr));
+            request.extensions_mut().insert(io);
+            let request = request.map(Body::new);
+            let format = ErrorFormat::negotiate(request.headers());
+            let context = ErrorContext::from_request(&request);
+            let unavailable =
+                || errors.create_response_with(StatusCode::SERVICE_UNAVAILABLE, format, &context);
+
+            let (responder, response) = async_channel::bounded(1);
+            if dispatch.send((request, responder)).await.is_err() {
+                return Ok::<_, Infallible>(unavailable());
+            }
+            Ok(response.recv().await.unwrap_or_else(|_| unavailable()))
+        }
+    });
+
+    let mut connection = pin!(http1::Builder::new()
+        .timer(SmolTimer::new())
+        .serve_connection(FuturesIo::new(client), service)
+        .with_upgrades());
+    let result = match select(connection.as_mut(), pin!(draining.recv())).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => {
+            // Idle keep-alive connections close right away, busy ones after their response
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+    if let Err(err) = result {
+        debug!("Connection from {} closed with: {}", peer_addr, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_runs_tasks_off_thread() {
+        let pool = IoPool::new(0, 2).unwrap();
+        let caller = std::thread::current().id();
+        let worker = async_io::block_on(pool.spawn(async { std::thread::current().id() }));
+        assert_ne!(worker, caller);
+    }
+}
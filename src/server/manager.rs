@@ -1,4 +1,5 @@
 use async_io::Async;
+use axum::routing::MethodRouter;
 use axum::Router;
 use bevy_defer::{AccessResult, AsyncAccess, AsyncCommandsExtension, AsyncExecutor, AsyncWorld};
 use bevy_ecs::prelude::*;
@@ -9,8 +10,13 @@ use std::{
     time::Duration,
 };
 
-use super::{ServerStatus, TaskType};
-use crate::{WebPort, WebServer, WebServerError, WebServerResult};
+use super::{IoMode, ServerStatsSnapshot, ServerStatus, TaskType};
+use crate::deterministic::DeterministicLayer;
+use crate::error::HttpErrorResponses;
+use crate::waker::wake_app_on_progress;
+#[cfg(feature = "compression")]
+use crate::CompressionConfig;
+use crate::{AccessLogConfig, WebPort, WebServer, WebServerError, WebServerResult};
 
 /// Resource to track running server tasks with shutdown capabilities
 #[derive(Default, Resource)]
@@ -158,6 +164,19 @@ impl WebServerManager {
             .collect()
     }
 
+    /// Get a snapshot of the request statistics of every server
+    pub fn server_stats_report(&self) -> Vec<(WebPort, ServerStatsSnapshot)> {
+        self.0
+            .iter()
+            .map(|(port, server)| (*port, server.stats().snapshot()))
+            .collect()
+    }
+
+    /// Get a snapshot of the request statistics for a server
+    pub fn server_stats(&self, port: &WebPort) -> Option<ServerStatsSnapshot> {
+        self.0.get(port).map(|server| server.stats().snapshot())
+    }
+
     pub fn stop_all(&mut self) {
         for (_, server) in self.0.iter_mut() {
             server.stop();
@@ -273,6 +292,68 @@ impl WebServerManager {
             .collect()
     }
 
+    /// Configure the access log of a server; takes effect the next time the server starts
+    pub fn set_access_log(&mut self, port: &WebPort, access_log: Option<AccessLogConfig>) {
+        if let Some(server) = self.0.get_mut(port) {
+            server.set_access_log(access_log);
+        } else {
+            error!("No server found on port {}", port);
+        }
+    }
+
+    /// Configure response compression of a server; takes effect the next time the server starts
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, port: &WebPort, compression: Option<CompressionConfig>) {
+        if let Some(server) = self.0.get_mut(port) {
+            server.set_compression(compression);
+        } else {
+            error!("No server found on port {}", port);
+        }
+    }
+
+    /// Override the app's error pages for a server; takes effect the next time the server starts
+    pub fn set_error_responses(
+        &mut self,
+        port: &WebPort,
+        error_responses: Option<HttpErrorResponses>,
+    ) {
+        if let Some(server) = self.0.get_mut(port) {
+            server.set_error_responses(error_responses);
+        } else {
+            error!("No server found on port {}", port);
+        }
+    }
+
+    /// Replace the 405 page of a server; takes effect the next time the server starts
+    pub fn set_method_not_allowed_fallback(&mut self, port: &WebPort, fallback: MethodRouter) {
+        if let Some(server) = self.0.get_mut(port) {
+            server.set_method_not_allowed_fallback(fallback);
+        } else {
+            error!("No server found on port {}", port);
+        }
+    }
+
+    /// Choose where a server drives its network I/O; takes effect the next time the server starts
+    pub fn set_io_mode(&mut self, port: &WebPort, io_mode: IoMode) {
+        if let Some(server) = self.0.get_mut(port) {
+            server.set_io_mode(io_mode);
+        } else {
+            error!("No server found on port {}", port);
+        }
+    }
+
+    pub(crate) fn set_deterministic(
+        &mut self,
+        port: &WebPort,
+        deterministic: Option<DeterministicLayer>,
+    ) {
+        if let Some(server) = self.0.get_mut(port) {
+            server.set_deterministic(deterministic);
+        } else {
+            error!("No server found on port {}", port);
+        }
+    }
+
     pub fn router(&self, port: &WebPort) -> Option<&Router> {
         self.0.get(port).map(|server| server.router())
     }
@@ -328,7 +409,7 @@ impl WebServerManager {
         server.set_status(ServerStatus::Starting);
 
         // We'll handle bind errors in the async task
-        let server_task = executor.spawn_task({
+        let server_task = executor.spawn_task(wake_app_on_progress({
             async move {
                 if let Err(err) = WebServer::run(port).await {
                     error!("bevy_webserver on port {} failed with: {}", port, err);
@@ -363,7 +444,7 @@ impl WebServerManager {
                 }
                 Ok(())
             }
-        });
+        }));
 
         let server = self
             .0
@@ -1,4 +1,6 @@
 use async_io::Async;
+use axum::extract::{ConnectInfo, Request};
+use axum::routing::{any, MethodRouter};
 use axum::Router;
 use bevy_defer::{AccessError, AsyncAccess, AsyncExecutor, AsyncWorld};
 use bevy_ecs::prelude::*;
@@ -8,23 +10,41 @@ use hyper_util::service::TowerToHyperService;
 use smol_hyper::rt::{FuturesIo, SmolTimer};
 use std::net::{IpAddr, TcpListener};
 use std::time::{Duration, Instant};
-
+use tower::ServiceExt;
+
+use crate::access_log::{AccessLogConfig, AccessLogLayer};
+use crate::catch_panic::CatchPanicLayer;
+#[cfg(feature = "compression")]
+use crate::compression::{CompressionConfig, CompressionLayer};
+use crate::deterministic::DeterministicLayer;
+use crate::error::{
+    method_not_allowed_fallback, not_found_fallback, ErrorNegotiationLayer, HttpErrorResponses,
+    MatchedRouteLayer,
+};
+use crate::request_context::RequestIdLayer;
+use crate::waker::{wake_app_on_progress, WebServerWaker};
 use crate::{WebServerError, WebServerResult};
 
 const RETRY_DELAY_SECONDS: u64 = 10;
 const MAX_RETRY_ATTEMPTS: usize = 100; // Allow up to 100 retry attempts
 
 mod connection_tracker;
+mod io;
 mod manager;
 mod port;
+mod stats;
 mod status;
 mod task_store;
 
+pub use io::IoMode;
 pub use manager::WebServerManager;
 pub use port::*;
+pub use stats::{ServerStats, ServerStatsSnapshot, LATENCY_BUCKETS_MS};
 pub use status::*;
 
 pub(crate) use connection_tracker::*;
+pub(crate) use io::IoExecutor;
+pub(crate) use stats::StatsLayer;
 pub(crate) use task_store::*;
 
 #[derive(Debug)]
@@ -35,6 +55,14 @@ pub struct WebServer {
     status: ServerStatus,
     task_store: TaskStore,
     connection_tracker: ConnectionTracker,
+    stats: ServerStats,
+    access_log: Option<AccessLogConfig>,
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionConfig>,
+    deterministic: Option<DeterministicLayer>,
+    io_mode: IoMode,
+    method_not_allowed: MethodRouter,
+    error_responses: Option<HttpErrorResponses>,
     last_error: Option<String>,
     retry_count: usize,
     next_retry_time: Option<Instant>,
@@ -49,6 +77,15 @@ impl Clone for WebServer {
             status: self.status,
             task_store: Default::default(),
             connection_tracker: ConnectionTracker::default(),
+            // A handle to the same counters, so the clone reports the live server's requests
+            stats: self.stats.clone(),
+            access_log: self.access_log.clone(),
+            #[cfg(feature = "compression")]
+            compression: self.compression.clone(),
+            deterministic: self.deterministic.clone(),
+            io_mode: self.io_mode,
+            method_not_allowed: self.method_not_allowed.clone(),
+            error_responses: self.error_responses.clone(),
             last_error: self.last_error.clone(),
             retry_count: self.retry_count,
             next_retry_time: self.next_retry_time,
@@ -66,12 +103,25 @@ impl WebServer {
             status: ServerStatus::default(),
             task_store: Default::default(),
             connection_tracker: ConnectionTracker::default(),
+            stats: ServerStats::default(),
+            access_log: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            deterministic: None,
+            io_mode: IoMode::default(),
+            method_not_allowed: any(method_not_allowed_fallback),
+            error_responses: None,
             last_error: None,
             retry_count: 0,
             next_retry_time: None,
         }
     }
 
+    /// Empty router answering unknown paths with the 404 page from [`HttpErrorResponses`](crate::HttpErrorResponses)
+    pub fn default_router() -> Router {
+        Router::new().fallback(not_found_fallback)
+    }
+
     pub fn ip(&self) -> IpAddr {
         self.ip
     }
@@ -96,7 +146,66 @@ impl WebServer {
         &mut self.router
     }
 
-    /// Immediately stop the server and cancel all tasks
+    /// Request statistics collected for this server
+    pub fn stats(&self) -> &ServerStats {
+        &self.stats
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLogConfig> {
+        self.access_log.as_ref()
+    }
+
+    /// Enable or disable the access log; takes effect the next time the server starts
+    pub fn set_access_log(&mut self, access_log: Option<AccessLogConfig>) {
+        self.access_log = access_log;
+    }
+
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<&CompressionConfig> {
+        self.compression.as_ref()
+    }
+
+    /// Enable or disable response compression; takes effect the next time the server starts
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, compression: Option<CompressionConfig>) {
+        self.compression = compression;
+    }
+
+    /// Hold mutating requests until a schedule releases them, see
+    /// [`WebServerAppExt::port_deterministic`](crate::WebServerAppExt::port_deterministic);
+    /// takes effect the next time the server starts
+    pub(crate) fn set_deterministic(&mut self, deterministic: Option<DeterministicLayer>) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn io_mode(&self) -> IoMode {
+        self.io_mode
+    }
+
+    /// Choose where the server drives its network I/O; takes effect the next time the server starts
+    pub fn set_io_mode(&mut self, io_mode: IoMode) {
+        self.io_mode = io_mode;
+    }
+
+    /// Handle requests to known paths with an unsupported method, in place of the 405 page;
+    /// takes effect the next time the server starts
+    pub fn set_method_not_allowed_fallback(&mut self, fallback: MethodRouter) {
+        self.method_not_allowed = fallback;
+    }
+
+    /// Error pages used by this server instead of the app's [`HttpErrorResponses`] resource
+    pub fn error_responses(&self) -> Option<&HttpErrorResponses> {
+        self.error_responses.as_ref()
+    }
+
+    /// Override the app's error pages for this server; takes effect the next time the server starts
+    pub fn set_error_responses(&mut self, error_responses: Option<HttpErrorResponses>) {
+        self.error_responses = error_responses;
+    }
+
+    /// Immediately stop the server and cancel all tasks.
+    ///
+    /// Open WebSockets are dropped without a Close frame, use [`Self::graceful_shutdown`] to close them properly.
     pub fn stop(&mut self) {
         self.task_store_mut().clear();
         self.set_status(ServerStatus::Stopped);
@@ -193,6 +302,42 @@ impl WebServer {
         self.connection_tracker.new_connection()
     }
 
+    /// Router actually served to connections, with the internal layers installed
+    pub(crate) fn service_router(&self) -> Router {
+        // Only reaches routes registered so far and keeps their own method fallbacks
+        let mut router = self
+            .router
+            .clone()
+            .method_not_allowed_fallback(self.method_not_allowed.clone());
+        // Tells the empty 404 of a router without a fallback from a handler's own
+        if router.has_routes() {
+            router = router.route_layer(MatchedRouteLayer);
+        }
+
+        // Innermost, so only the handler is held; the stats count a held request as in
+        // flight and include the hold in its latency, as the client experiences it
+        if let Some(deterministic) = &self.deterministic {
+            router = router.layer(deterministic.clone());
+        }
+
+        // Inside the stats layer, so recovered panics are counted as 500 responses
+        router = router.layer(CatchPanicLayer::new(self.stats.clone()));
+        router = router.layer(ErrorNegotiationLayer::new(self.error_responses.clone()));
+        // Outside error negotiation, so error pages are compressed in their final format
+        #[cfg(feature = "compression")]
+        if let Some(compression) = &self.compression {
+            router = router.layer(CompressionLayer::new(compression.clone()));
+        }
+        router = router.layer(StatsLayer::new(self.stats.clone()));
+
+        if let Some(access_log) = &self.access_log {
+            router = router.layer(AccessLogLayer::new(access_log.clone()));
+        }
+
+        // Outermost, so every other layer and handler runs inside the request span
+        router.layer(RequestIdLayer)
+    }
+
     pub(crate) fn status(&self) -> ServerStatus {
         self.status
     }
@@ -265,18 +410,15 @@ impl WebServer {
 
                 let ip = server.ip();
                 let port = server.port();
-                let router = server.router().clone();
+                let router = server.service_router();
 
                 Ok::<_, AccessError>((ip, port, router))
             })
             .map_err(|e| WebServerError::from(e))??)
     }
 
-    async fn listen_accept_loop(ip: IpAddr, port: WebPort, router: Router) -> WebServerResult<()> {
-        let async_executor = AsyncWorld
-            .non_send_resource::<AsyncExecutor>()
-            .get(|executor| executor.clone())?;
-
+    /// Bind the listener and mark the server as running
+    async fn bind(ip: IpAddr, port: WebPort) -> WebServerResult<Async<TcpListener>> {
         let listener = Async::<TcpListener>::bind((ip, port)).map_err(|e| {
             error!("Failed to bind server on {}:{}: {}", ip, port, e);
             WebServerError::bind_failed(ip, port, e)
@@ -294,9 +436,16 @@ impl WebServer {
                 Ok::<(), AccessError>(())
             })??;
 
-        info!("Web server listening on {}:{}", ip, port);
+        Ok(listener)
+    }
 
-        let service = TowerToHyperService::new(router);
+    async fn listen_accept_loop(ip: IpAddr, port: WebPort, router: Router) -> WebServerResult<()> {
+        let async_executor = AsyncWorld
+            .non_send_resource::<AsyncExecutor>()
+            .get(|executor| executor.clone())?;
+
+        let listener = Self::bind(ip, port).await?;
+        info!("Web server listening on {}:{}", ip, port);
 
         loop {
             // Check if shutdown is requested before accepting new connections
@@ -321,7 +470,7 @@ impl WebServer {
             let accept_result = listener.accept().await;
 
             match accept_result {
-                Ok((client, _sock_addr)) => {
+                Ok((client, peer_addr)) => {
                     let connection_id =
                         AsyncWorld.resource::<WebServerManager>().get(|manager| {
                             manager
@@ -331,8 +480,14 @@ impl WebServer {
                         })??;
 
                     // Connection handling task
-                    let connection_task = async_executor.spawn_task({
-                        let service = service.clone();
+                    let connection_task = async_executor.spawn_task(wake_app_on_progress({
+                        // Expose the peer address to handlers and layers through `ConnectInfo`
+                        let service = TowerToHyperService::new(router.clone().map_request(
+                            move |mut request: Request<hyper::body::Incoming>| {
+                                request.extensions_mut().insert(ConnectInfo(peer_addr));
+                                request
+                            },
+                        ));
 
                         let port = port;
 
@@ -350,7 +505,8 @@ impl WebServer {
 
                             let connection = http1::Builder::new()
                                 .timer(SmolTimer::new())
-                                .serve_connection(FuturesIo::new(client), service);
+                                .serve_connection(FuturesIo::new(client), service)
+                                .with_upgrades();
 
                             let result = connection.await;
                             let duration = start_time.elapsed();
@@ -392,7 +548,7 @@ impl WebServer {
 
                             Ok(())
                         }
-                    });
+                    }));
 
                     // Add task to TaskStore
                     AsyncWorld
@@ -415,6 +571,10 @@ impl WebServer {
                         .await;
                 }
             }
+            // The yield only resumes on the next update, which on-demand apps have to be asked for
+            if let Some(waker) = WebServerWaker::current() {
+                waker.wake();
+            }
             AsyncWorld.yield_now().await;
         }
     }
@@ -431,7 +591,18 @@ impl WebServer {
             return Err(test_error);
         }
 
-        Self::listen_accept_loop(ip, port, router).await?;
+        let io_mode = AsyncWorld.resource::<WebServerManager>().get(|manager| {
+            manager
+                .get_server(&port)
+                .map(|server| server.io_mode())
+                .unwrap_or_default()
+        })?;
+        match io_mode {
+            IoMode::Main => Self::listen_accept_loop(ip, port, router).await?,
+            IoMode::Dedicated { threads } => {
+                Self::listen_dedicated(ip, port, router, threads).await?
+            }
+        }
 
         Ok(())
     }
@@ -461,8 +632,11 @@ impl From<WebServerConfig> for WebServerManager {
             config.ip, config.port
         );
         let mut servers = WebServerManager::default();
-        if let Err(err) = servers.add_server(WebServer::new(config.ip, config.port, Router::new()))
-        {
+        if let Err(err) = servers.add_server(WebServer::new(
+            config.ip,
+            config.port,
+            WebServer::default_router(),
+        )) {
             error!(
                 "Failed to add server on {}:{}: {}",
                 config.ip, config.port, err
@@ -0,0 +1,313 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::StatusCode,
+    response::Response,
+};
+use http_body_util::BodyExt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+/// Upper bounds (in milliseconds) of the latency histogram buckets.
+/// Requests slower than the last bound are counted in an implicit `+Inf` bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 12] =
+    [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Debug, Default)]
+struct StatsCounters {
+    total_requests: AtomicU64,
+    in_flight: AtomicU64,
+    status_classes: [AtomicU64; 5],
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    latency_sum_us: AtomicU64,
+    handler_panics: AtomicU64,
+}
+
+/// Per-server request statistics, shared between the server and its request layer.
+///
+/// Cloning a `ServerStats` yields a handle to the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct ServerStats {
+    counters: Arc<StatsCounters>,
+}
+
+impl ServerStats {
+    pub fn total_requests(&self) -> u64 {
+        self.counters.total_requests.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.counters.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_in(&self) -> u64 {
+        self.counters.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.counters.bytes_out.load(Ordering::Relaxed)
+    }
+
+    /// Number of handlers that panicked and were answered with a 500 instead
+    pub fn handler_panics(&self) -> u64 {
+        self.counters.handler_panics.load(Ordering::Relaxed)
+    }
+
+    /// Take a consistent-enough copy of all counters for reporting
+    pub fn snapshot(&self) -> ServerStatsSnapshot {
+        let counters = &self.counters;
+        let mut responses_by_class = [0; 5];
+        for (count, counter) in responses_by_class
+            .iter_mut()
+            .zip(counters.status_classes.iter())
+        {
+            *count = counter.load(Ordering::Relaxed);
+        }
+
+        let mut latency_buckets = [0; LATENCY_BUCKETS_MS.len() + 1];
+        for (count, counter) in latency_buckets
+            .iter_mut()
+            .zip(counters.latency_buckets.iter())
+        {
+            *count = counter.load(Ordering::Relaxed);
+        }
+
+        ServerStatsSnapshot {
+            total_requests: counters.total_requests.load(Ordering::Relaxed),
+            in_flight: counters.in_flight.load(Ordering::Relaxed),
+            responses_by_class,
+            bytes_in: counters.bytes_in.load(Ordering::Relaxed),
+            bytes_out: counters.bytes_out.load(Ordering::Relaxed),
+            latency_buckets,
+            latency_sum: Duration::from_micros(counters.latency_sum_us.load(Ordering::Relaxed)),
+            handler_panics: counters.handler_panics.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn request_started(&self) -> RequestGuard {
+        self.counters.total_requests.fetch_add(1, Ordering::SeqCst);
+        self.counters.in_flight.fetch_add(1, Ordering::SeqCst);
+
+        RequestGuard {
+            stats: self.clone(),
+            start_time: Instant::now(),
+        }
+    }
+
+    pub(crate) fn record_panic(&self) {
+        self.counters.handler_panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_response(&self, status: StatusCode, latency: Duration) {
+        let class = (status.as_u16() / 100).clamp(1, 5) as usize - 1;
+        self.counters.status_classes[class].fetch_add(1, Ordering::Relaxed);
+
+        let latency_ms = latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&upper| latency_ms <= upper)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counters.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .latency_sum_us
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn count_body(&self, body: Body, counter: fn(&StatsCounters) -> &AtomicU64) -> Body {
+        let stats = self.clone();
+        Body::new(body.map_frame(move |frame| {
+            if let Some(data) = frame.data_ref() {
+                counter(&stats.counters).fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+            frame
+        }))
+    }
+}
+
+/// RAII guard that keeps a request counted as in-flight until it is dropped
+pub(crate) struct RequestGuard {
+    stats: ServerStats,
+    start_time: Instant,
+}
+
+impl RequestGuard {
+    fn finish(self, status: StatusCode) {
+        self.stats
+            .record_response(status, self.start_time.elapsed());
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.stats.counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Point-in-time copy of a server's [`ServerStats`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerStatsSnapshot {
+    pub total_requests: u64,
+    pub in_flight: u64,
+    /// Responses per status class, `[1xx, 2xx, 3xx, 4xx, 5xx]`
+    pub responses_by_class: [u64; 5],
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Per-bucket (non-cumulative) latency counts matching [`LATENCY_BUCKETS_MS`],
+    /// with the last entry counting requests slower than every bound
+    pub latency_buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    pub latency_sum: Duration,
+    pub handler_panics: u64,
+}
+
+impl ServerStatsSnapshot {
+    /// Number of responses with the given status class (1 to 5)
+    pub fn responses_in_class(&self, class: u16) -> u64 {
+        match class {
+            1..=5 => self.responses_by_class[class as usize - 1],
+            _ => 0,
+        }
+    }
+
+    /// Number of completed requests that have a recorded latency
+    pub fn latency_count(&self) -> u64 {
+        self.latency_buckets.iter().sum()
+    }
+
+    /// Average latency of completed requests, if any
+    pub fn average_latency(&self) -> Option<Duration> {
+        let count = self.latency_count();
+        (count > 0)
+            .then(|| Duration::from_nanos((self.latency_sum.as_nanos() / count as u128) as u64))
+    }
+}
+
+/// Tower layer that records every request passing through a server's router
+#[derive(Clone)]
+pub(crate) struct StatsLayer {
+    stats: ServerStats,
+}
+
+impl StatsLayer {
+    pub(crate) fn new(stats: ServerStats) -> Self {
+        Self { stats }
+    }
+}
+
+impl<S> Layer<S> for StatsLayer {
+    type Service = StatsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StatsService {
+            inner,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct StatsService<S> {
+    inner: S,
+    stats: ServerStats,
+}
+
+impl<S> Service<Request> for StatsService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let guard = self.stats.request_started();
+        let stats = self.stats.clone();
+
+        let request = request.map(|body| {
+            if body.size_hint().exact() == Some(0) {
+                body
+            } else {
+                stats.count_body(body, |counters| &counters.bytes_in)
+            }
+        });
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            guard.finish(response.status());
+
+            Ok(response.map(|body| stats.count_body(body, |counters| &counters.bytes_out)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_guard_tracks_in_flight() {
+        let stats = ServerStats::default();
+
+        let guard1 = stats.request_started();
+        let guard2 = stats.request_started();
+        assert_eq!(stats.total_requests(), 2);
+        assert_eq!(stats.in_flight(), 2);
+
+        guard1.finish(StatusCode::OK);
+        assert_eq!(stats.in_flight(), 1);
+
+        // A dropped request (e.g. client went away) is no longer in flight
+        // but doesn't count as a response
+        drop(guard2);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.in_flight, 0);
+        assert_eq!(snapshot.total_requests, 2);
+        assert_eq!(snapshot.latency_count(), 1);
+    }
+
+    #[test]
+    fn test_status_classes() {
+        let stats = ServerStats::default();
+        for status in [
+            StatusCode::OK,
+            StatusCode::CREATED,
+            StatusCode::NOT_MODIFIED,
+            StatusCode::NOT_FOUND,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ] {
+            stats.request_started().finish(status);
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.responses_by_class, [0, 2, 1, 1, 1]);
+        assert_eq!(snapshot.responses_in_class(2), 2);
+        assert_eq!(snapshot.responses_in_class(6), 0);
+    }
+
+    #[test]
+    fn test_latency_buckets() {
+        let stats = ServerStats::default();
+        stats.record_response(StatusCode::OK, Duration::from_micros(500));
+        stats.record_response(StatusCode::OK, Duration::from_millis(30));
+        stats.record_response(StatusCode::OK, Duration::from_secs(60));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.latency_buckets[0], 1);
+        assert_eq!(snapshot.latency_buckets[4], 1);
+        assert_eq!(snapshot.latency_buckets[LATENCY_BUCKETS_MS.len()], 1);
+        assert_eq!(snapshot.latency_count(), 3);
+        assert!(snapshot.average_latency().unwrap() > Duration::from_secs(20));
+    }
+}
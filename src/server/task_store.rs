@@ -1,5 +1,6 @@
 use bevy_defer::{AccessResult, Task};
 use bevy_derive::{Deref, DerefMut};
+use bevy_ecs::entity::Entity;
 use dashmap::DashMap;
 use std::sync::Arc;
 
@@ -7,6 +8,9 @@ use std::sync::Arc;
 pub(crate) enum TaskType {
     Server,
     Connection(usize),
+    /// Handler of a request dispatched from an [`IoMode::Dedicated`](crate::IoMode) server
+    Request(usize),
+    WebSocket(Entity),
 }
 
 #[derive(Clone, Debug, Default, Deref, DerefMut)]
@@ -0,0 +1,466 @@
+use async_channel::TrySendError;
+use async_io::Timer;
+use axum::{
+    http::HeaderMap,
+    response::{
+        sse::{Event as SseEvent, Sse},
+        IntoResponse, Response,
+    },
+};
+use bevy_ecs::prelude::*;
+use bevy_log::{debug, warn};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Header sent by reconnecting `EventSource` clients with the id of the last message they saw
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// Event sent instead of a partial replay to a client whose missed messages are gone
+pub(crate) const RESET_EVENT: &str = "reset";
+
+/// Buffering limits of a Server-Sent Events endpoint
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SseConfig {
+    /// Messages queued per client before it is considered too slow and disconnected.
+    /// Browsers reconnect automatically and resume from the history.
+    pub client_buffer: usize,
+    /// Recent messages kept for clients resuming with a `Last-Event-ID` header
+    pub history: usize,
+    /// Idle time after which a comment is sent, so proxies don't close quiet streams
+    pub keep_alive: Duration,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        Self {
+            client_buffer: 64,
+            history: 256,
+            keep_alive: Duration::from_secs(15),
+        }
+    }
+}
+
+impl SseConfig {
+    pub fn with_client_buffer(mut self, client_buffer: usize) -> Self {
+        self.client_buffer = client_buffer.max(1);
+        self
+    }
+
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive.max(Duration::from_millis(1));
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SseMessage {
+    /// Resume id, `0` for messages that are not part of the history
+    pub(crate) id: u64,
+    /// SSE event name, `message` when unset
+    pub(crate) event: Option<&'static str>,
+    /// Entity the message is about, used to filter per-entity subscriptions
+    pub(crate) entity: Option<Entity>,
+    pub(crate) data: Arc<str>,
+}
+
+impl SseMessage {
+    pub(crate) fn new(
+        event: Option<&'static str>,
+        entity: Option<Entity>,
+        data: impl Into<Arc<str>>,
+    ) -> Self {
+        Self {
+            id: 0,
+            event,
+            entity,
+            data: data.into(),
+        }
+    }
+
+    fn is_for(&self, entity: Option<Entity>) -> bool {
+        entity.is_none() || self.entity.is_none() || self.entity == entity
+    }
+}
+
+struct SseSubscriber {
+    sender: async_channel::Sender<SseMessage>,
+    entity: Option<Entity>,
+}
+
+#[derive(Default)]
+struct SseChannelState {
+    next_id: u64,
+    history: VecDeque<SseMessage>,
+    subscribers: Vec<SseSubscriber>,
+}
+
+/// Fan-out of serialized messages to the clients of one SSE endpoint
+#[derive(Clone)]
+pub(crate) struct SseChannel {
+    config: SseConfig,
+    state: Arc<Mutex<SseChannelState>>,
+}
+
+impl SseChannel {
+    pub(crate) fn new(config: SseConfig) -> Self {
+        Self {
+            config,
+            state: Arc::default(),
+        }
+    }
+
+    /// Assign the next id to `message` and queue it for every interested subscriber,
+    /// dropping subscribers whose buffer is full
+    pub(crate) fn publish(&self, mut message: SseMessage) {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        state.next_id += 1;
+        message.id = state.next_id;
+
+        if self.config.history > 0 {
+            if state.history.len() >= self.config.history {
+                state.history.pop_front();
+            }
+            state.history.push_back(message.clone());
+        }
+
+        state.subscribers.retain(|subscriber| {
+            if !message.is_for(subscriber.entity) {
+                return !subscriber.sender.is_closed();
+            }
+            match subscriber.sender.try_send(message.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    debug!("Disconnecting SSE client that fell behind");
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+
+    /// Whether a client that last saw `last_event_id` can be caught up from the history,
+    /// instead of missing messages that rolled out of it or don't fit its buffer
+    pub(crate) fn can_resume(&self, last_event_id: u64) -> bool {
+        let state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.resumable(&state, last_event_id)
+    }
+
+    fn resumable(&self, state: &SseChannelState, last_event_id: u64) -> bool {
+        // Ids past the last one were handed out before a restart
+        let Some(missed) = state.next_id.checked_sub(last_event_id) else {
+            return false;
+        };
+        missed == 0
+            || (missed <= self.config.client_buffer as u64
+                && state
+                    .history
+                    .front()
+                    .is_some_and(|oldest| oldest.id <= last_event_id + 1))
+    }
+
+    pub(crate) fn keep_alive(&self) -> Duration {
+        self.config.keep_alive
+    }
+
+    /// Register a new client interested in `entity` (or everything), sending it `initial`
+    /// and then the history after `last_event_id` before any new message
+    pub(crate) fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+        entity: Option<Entity>,
+        initial: Vec<SseMessage>,
+    ) -> async_channel::Receiver<SseMessage> {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.subscribe_locked(&mut state, last_event_id, entity, initial)
+    }
+
+    /// Register a new client of an event stream, resuming after `last_event_id`.
+    /// A client that can't be caught up gets a [`RESET_EVENT`] carrying the current id
+    /// and then only new messages, rather than silently missing some.
+    pub(crate) fn subscribe_events(
+        &self,
+        last_event_id: Option<u64>,
+    ) -> async_channel::Receiver<SseMessage> {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match last_event_id {
+            Some(last_event_id) if !self.resumable(&state, last_event_id) => {
+                let mut reset = SseMessage::new(Some(RESET_EVENT), None, "{}");
+                reset.id = state.next_id;
+                self.subscribe_locked(&mut state, None, None, vec![reset])
+            }
+            last_event_id => self.subscribe_locked(&mut state, last_event_id, None, Vec::new()),
+        }
+    }
+
+    fn subscribe_locked(
+        &self,
+        state: &mut SseChannelState,
+        last_event_id: Option<u64>,
+        entity: Option<Entity>,
+        initial: Vec<SseMessage>,
+    ) -> async_channel::Receiver<SseMessage> {
+        let (sender, receiver) =
+            async_channel::bounded(self.config.client_buffer.max(1) + initial.len());
+
+        for message in initial {
+            let _ = sender.try_send(message);
+        }
+
+        if let Some(last_event_id) = last_event_id {
+            let missed: Vec<_> = state
+                .history
+                .iter()
+                .filter(|message| message.id > last_event_id && message.is_for(entity))
+                .collect();
+            // Replay as much of the tail as fits in the client buffer
+            let skip = missed.len().saturating_sub(self.config.client_buffer);
+            for message in missed.into_iter().skip(skip) {
+                let _ = sender.try_send(message.clone());
+            }
+        }
+
+        state.subscribers.push(SseSubscriber { sender, entity });
+        receiver
+    }
+}
+
+/// Id sent by a reconnecting client in the `Last-Event-ID` header
+pub(crate) fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Streaming response forwarding the messages of a subscription, with an empty comment
+/// after every `keep_alive` without one
+pub(crate) fn sse_response(
+    receiver: async_channel::Receiver<SseMessage>,
+    keep_alive: Duration,
+) -> Response {
+    let messages = receiver.map(|message| {
+        let mut event = SseEvent::default().data(&*message.data);
+        if message.id != 0 {
+            event = event.id(message.id.to_string());
+        }
+        if let Some(name) = message.event {
+            event = event.event(name);
+        }
+        Ok::<_, Infallible>(event)
+    });
+
+    Sse::new(KeepAliveStream {
+        messages: Box::pin(messages),
+        keep_alive,
+        timer: Timer::after(keep_alive),
+    })
+    .into_response()
+}
+
+/// axum's own keep-alive needs a tokio runtime, this one runs on any executor
+struct KeepAliveStream<S> {
+    messages: S,
+    keep_alive: Duration,
+    timer: Timer,
+}
+
+impl<S: Stream<Item = Result<SseEvent, Infallible>> + Unpin> Stream for KeepAliveStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Poll::Ready(message) = this.messages.poll_next_unpin(cx) {
+            this.timer.set_after(this.keep_alive);
+            return Poll::Ready(message);
+        }
+        if Pin::new(&mut this.timer).poll(cx).is_ready() {
+            this.timer.set_after(this.keep_alive);
+            return Poll::Ready(Some(Ok(SseEvent::default().comment(""))));
+        }
+        Poll::Pending
+    }
+}
+
+/// SSE endpoints fed by events of type `E`
+#[derive(Resource)]
+pub(crate) struct SseStreams<E> {
+    channels: Vec<SseChannel>,
+    marker: PhantomData<fn(E)>,
+}
+
+impl<E> Default for SseStreams<E> {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<E> SseStreams<E> {
+    pub(crate) fn push(&mut self, channel: SseChannel) {
+        self.channels.push(channel);
+    }
+}
+
+/// Serialize every `E` written this frame once and broadcast it to all of its endpoints
+pub(crate) fn broadcast_sse_events<E: Event + Serialize>(
+    mut events: EventReader<E>,
+    streams: Res<SseStreams<E>>,
+) {
+    for event in events.read() {
+        let data = match serde_json::to_string(event) {
+            Ok(data) => data,
+            Err(err) => {
+                warn!(
+                    "Failed to serialize {} for SSE: {}",
+                    std::any::type_name::<E>(),
+                    err
+                );
+                continue;
+            }
+        };
+
+        let message = SseMessage::new(None, None, data);
+        for channel in &streams.channels {
+            channel.publish(message.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(data: &str) -> SseMessage {
+        SseMessage::new(None, None, data)
+    }
+
+    #[test]
+    fn test_idle_streams_send_keep_alive_comments() {
+        let (sender, receiver) = async_channel::bounded(1);
+        let response = sse_response(receiver, Duration::from_millis(10));
+        let mut body = response.into_body().into_data_stream();
+        let mut next = || async_io::block_on(body.next()).map(|chunk| chunk.unwrap());
+
+        assert_eq!(next().unwrap(), ": \n\n");
+        sender.try_send(message("hello")).unwrap();
+        assert_eq!(next().unwrap(), "data: hello\n\n");
+        drop(sender);
+        assert!(next().is_none());
+    }
+
+    #[test]
+    fn test_resume_from_last_event_id() {
+        let channel = SseChannel::new(SseConfig::default().with_history(2));
+        channel.publish(message("a"));
+        channel.publish(message("b"));
+        channel.publish(message("c"));
+
+        let receiver = channel.subscribe(Some(1), None, Vec::new());
+        assert_eq!(&*receiver.try_recv().unwrap().data, "b");
+        assert_eq!(&*receiver.try_recv().unwrap().data, "c");
+        assert!(receiver.try_recv().is_err());
+
+        channel.publish(message("d"));
+        let message = receiver.try_recv().unwrap();
+        assert_eq!((message.id, &*message.data), (4, "d"));
+    }
+
+    #[test]
+    fn test_can_resume() {
+        let channel = SseChannel::new(SseConfig::default().with_history(2));
+        assert!(channel.can_resume(0));
+        channel.publish(message("a"));
+        channel.publish(message("b"));
+        channel.publish(message("c"));
+
+        assert!(channel.can_resume(3));
+        assert!(channel.can_resume(1));
+        // "a" rolled out of the history
+        assert!(!channel.can_resume(0));
+        assert!(!channel.can_resume(4));
+    }
+
+    #[test]
+    fn test_events_reset_after_gap() {
+        let channel = SseChannel::new(SseConfig::default().with_history(2));
+        channel.publish(message("a"));
+        channel.publish(message("b"));
+        channel.publish(message("c"));
+
+        let resumed = channel.subscribe_events(Some(1));
+        assert_eq!(&*resumed.try_recv().unwrap().data, "b");
+        assert_eq!(&*resumed.try_recv().unwrap().data, "c");
+
+        // "a" rolled out of the history, so replaying "b" and "c" would hide the gap
+        let reset = channel.subscribe_events(Some(0));
+        let message = reset.try_recv().unwrap();
+        assert_eq!((message.id, message.event), (3, Some(RESET_EVENT)));
+        assert!(reset.try_recv().is_err());
+
+        channel.publish(SseMessage::new(None, None, "d"));
+        assert_eq!(&*reset.try_recv().unwrap().data, "d");
+    }
+
+    #[test]
+    fn test_slow_client_is_dropped() {
+        let channel = SseChannel::new(SseConfig::default().with_client_buffer(1));
+        let slow = channel.subscribe(None, None, Vec::new());
+        let fast = channel.subscribe(None, None, Vec::new());
+
+        channel.publish(message("a"));
+        assert_eq!(&*fast.try_recv().unwrap().data, "a");
+        channel.publish(message("b"));
+
+        assert_eq!(&*slow.try_recv().unwrap().data, "a");
+        assert!(slow.try_recv().is_err());
+        assert!(slow.is_closed());
+        assert_eq!(&*fast.try_recv().unwrap().data, "b");
+    }
+
+    #[test]
+    fn test_entity_subscription() {
+        let channel = SseChannel::new(SseConfig::default());
+        let watched = Entity::from_raw(1);
+        let other = Entity::from_raw(2);
+        channel.publish(SseMessage::new(Some("changed"), Some(other), "old"));
+
+        let snapshot = SseMessage::new(Some("added"), Some(watched), "snapshot");
+        let receiver = channel.subscribe(Some(0), Some(watched), vec![snapshot.clone()]);
+        channel.publish(SseMessage::new(Some("changed"), Some(other), "other"));
+        channel.publish(SseMessage::new(Some("changed"), Some(watched), "watched"));
+
+        assert_eq!(receiver.try_recv().unwrap(), snapshot);
+        let message = receiver.try_recv().unwrap();
+        assert_eq!((message.id, &*message.data), (3, "watched"));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,14 +1,27 @@
+use crate::compression::ContentEncoding;
 use crate::error::HttpErrorResponses;
 use axum::{
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    extract::Path as UriPath,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
 };
 use bevy_app::{App, Plugin};
 use bevy_defer::{AsyncAccess, AsyncWorld};
 use bevy_derive::{Deref, DerefMut};
 use bevy_ecs::prelude::*;
-use bevy_log::error;
-use std::{collections::HashSet, fs};
+use bevy_log::{error, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod embedded;
+mod range;
+
+pub use embedded::{EmbeddedDir, EmbeddedFile};
 
 pub struct WebStaticAssetsPlugin;
 
@@ -20,9 +33,20 @@ impl Plugin for WebStaticAssetsPlugin {
     }
 }
 
+/// Cache-Control sent for static assets without a policy of their own
+const DEFAULT_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Extensions of files served with a `Cache-Control` header, and the policy each one gets.
+///
+/// Extensions in the set use the default policy, `public, max-age=3600`, unless
+/// [`set_cache_control`](Self::set_cache_control) gave them their own.
 #[derive(Clone, Deref, DerefMut, Resource)]
 pub struct WebStaticFileExtensions {
+    #[deref]
     extensions: HashSet<String>,
+    cache_control: HashMap<String, String>,
+    default_cache_control: String,
+    compressible: HashSet<String>,
 }
 
 impl WebStaticFileExtensions {
@@ -30,6 +54,11 @@ impl WebStaticFileExtensions {
         "css", "js", "png", "jpg", "jpeg", "gif", "svg", "ico", "woff", "woff2", "ttf", "eot",
         "pdf", "webp", "avif",
     ];
+    /// Text formats that shrink well; images, fonts and archives are compressed already
+    const DEFAULT_COMPRESSIBLE: [&'static str; 14] = [
+        "html", "htm", "css", "js", "mjs", "json", "map", "svg", "txt", "xml", "csv", "md", "wasm",
+        "ttf",
+    ];
     pub fn new() -> Self {
         Self::default()
     }
@@ -41,6 +70,7 @@ impl WebStaticFileExtensions {
     {
         Self {
             extensions: extensions.into_iter().map(|s| s.into()).collect(),
+            ..Self::default()
         }
     }
 
@@ -60,11 +90,42 @@ impl WebStaticFileExtensions {
         self.extensions.clear();
     }
 
+    /// Send `cache_control` for files with `extension`, e.g. `no-cache` for `html`
+    pub fn set_cache_control(
+        &mut self,
+        extension: impl Into<String>,
+        cache_control: impl Into<String>,
+    ) -> &mut Self {
+        self.cache_control
+            .insert(extension.into(), cache_control.into());
+        self
+    }
+
+    /// Policy for extensions in the set without one of their own
+    pub fn set_default_cache_control(&mut self, cache_control: impl Into<String>) -> &mut Self {
+        self.default_cache_control = cache_control.into();
+        self
+    }
+
+    /// `Cache-Control` value for files with `extension`, if they get one
+    pub fn cache_control(&self, extension: &str) -> Option<&str> {
+        self.cache_control
+            .get(extension)
+            .map(String::as_str)
+            .or_else(|| {
+                self.contains(extension)
+                    .then_some(self.default_cache_control.as_str())
+            })
+    }
+
     pub async fn is_static_asset(file_path: &str) -> bool {
         if let Some(extension) = std::path::Path::new(file_path)
             .extension()
             .and_then(|ext| ext.to_str())
         {
+            if !bevy_defer::in_async_context() {
+                return Self::DEFAULT_EXTENSIONS.contains(&extension);
+            }
             // Try to get the static extensions from the resource
             match AsyncWorld
                 .resource::<WebStaticFileExtensions>()
@@ -80,6 +141,50 @@ impl WebStaticFileExtensions {
             false
         }
     }
+
+    /// Whether files with `extension` are worth compressing
+    pub fn is_compressible(&self, extension: &str) -> bool {
+        self.compressible.contains(extension)
+    }
+
+    pub fn add_compressible<S: Into<String>>(&mut self, extension: S) {
+        self.compressible.insert(extension.into());
+    }
+
+    pub fn remove_compressible(&mut self, extension: &str) {
+        self.compressible.remove(extension);
+    }
+
+    /// Whether responses with `content_type` are worth compressing, judged by the
+    /// extensions registered for it
+    pub fn is_compressible_type(&self, content_type: &str) -> bool {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        mime_guess::get_mime_extensions_str(&essence).is_some_and(|extensions| {
+            extensions
+                .iter()
+                .any(|extension| self.is_compressible(extension))
+        })
+    }
+
+    /// Run `f` on the app's resource, or on the defaults if it is unavailable
+    pub(crate) fn with_current<T>(f: impl FnOnce(&Self) -> T) -> T {
+        let mut f = Some(f);
+        if bevy_defer::in_async_context() {
+            let current = AsyncWorld
+                .resource::<WebStaticFileExtensions>()
+                .get(|extensions| f.take().map(|f| f(extensions)));
+            if let Ok(Some(output)) = current {
+                return output;
+            }
+        }
+        let f = f.expect("`f` is only taken when the resource exists");
+        f(&Self::default())
+    }
 }
 
 impl Default for WebStaticFileExtensions {
@@ -89,51 +194,665 @@ impl Default for WebStaticFileExtensions {
                 .iter()
                 .map(|&s| s.to_string())
                 .collect(),
+            cache_control: HashMap::new(),
+            default_cache_control: DEFAULT_CACHE_CONTROL.to_string(),
+            compressible: Self::DEFAULT_COMPRESSIBLE
+                .iter()
+                .map(|&s| s.to_string())
+                .collect(),
         }
     }
 }
 
+/// Validators of a file, compared against conditional request headers
+struct Validators {
+    etag: String,
+    modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Strong ETag built from the file's size and modification time
+    fn new(metadata: &fs::Metadata) -> Self {
+        let since_epoch = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+        Self {
+            etag: format!(
+                "\"{:x}-{:x}\"",
+                metadata.len(),
+                since_epoch.map_or(0, |since_epoch| since_epoch.as_nanos())
+            ),
+            // HTTP dates have whole-second precision
+            modified: since_epoch
+                .map(|since_epoch| UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs())),
+        }
+    }
+
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Some(modified) = self.modified {
+            if let Ok(modified) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+                headers.insert(header::LAST_MODIFIED, modified);
+            }
+        }
+    }
+
+    /// Whether a `Range` request may be served partially: it has no `If-Range` header,
+    /// or one naming the current strong ETag or exact modification date
+    fn if_range_matches(&self, request_headers: &HeaderMap) -> bool {
+        let Some(if_range) = request_headers.get(header::IF_RANGE) else {
+            return true;
+        };
+        let Ok(if_range) = if_range.to_str() else {
+            return false;
+        };
+        if if_range.starts_with('"') {
+            return if_range == self.etag;
+        }
+        httpdate::parse_http_date(if_range)
+            .is_ok_and(|date| self.modified.is_some_and(|modified| modified == date))
+    }
+
+    /// Whether the client's cached copy is current. `If-None-Match` takes precedence
+    /// over `If-Modified-Since`, as RFC 9110 requires.
+    fn not_modified(&self, request_headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+            return if_none_match.to_str().is_ok_and(|tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag)
+            });
+        }
+
+        let since = request_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|since| since.to_str().ok())
+            .and_then(|since| httpdate::parse_http_date(since).ok());
+        matches!((self.modified, since), (Some(modified), Some(since)) if modified <= since)
+    }
+}
+
+/// How files with a given extension are served, from [`WebStaticFileExtensions`]
+struct FilePolicy {
+    cache_control: Option<String>,
+    compressible: bool,
+}
+
+impl FilePolicy {
+    fn for_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        WebStaticFileExtensions::with_current(|extensions| Self {
+            cache_control: extensions.cache_control(extension).map(str::to_string),
+            compressible: extensions.is_compressible(extension),
+        })
+    }
+}
+
+/// Response with the contents of the file at `path`, or `None` if it can't be read.
+/// Answers `304 Not Modified` when the conditional headers in `request_headers` match,
+/// and `206 Partial Content` for `Range` requests.
+async fn file_response(path: &Path, request_headers: &HeaderMap) -> Option<Response> {
+    let policy = FilePolicy::for_path(path);
+    let precompressed = match policy.compressible {
+        true => precompressed_sibling(path, request_headers).await,
+        false => None,
+    };
+    let file = match &precompressed {
+        Some((_, sibling)) => async_fs::File::open(sibling).await.ok()?,
+        None => async_fs::File::open(path).await.ok()?,
+    };
+    let metadata = file.metadata().await.ok().filter(fs::Metadata::is_file)?;
+
+    Some(content_response(
+        path,
+        range::Content::File(file),
+        metadata.len(),
+        &Validators::new(&metadata),
+        policy,
+        precompressed.map(|(encoding, _)| encoding),
+        request_headers,
+    ))
+}
+
+/// Response with the `len` bytes of `content`, typed by the name of `path` and encoded
+/// with `encoding`, shared by files on disk and [`EmbeddedDir`]s
+fn content_response(
+    path: &Path,
+    content: range::Content,
+    len: u64,
+    validators: &Validators,
+    policy: FilePolicy,
+    encoding: Option<ContentEncoding>,
+    request_headers: &HeaderMap,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    validators.insert_headers(&mut headers);
+    if let Some(cache_control) = policy.cache_control {
+        if let Ok(cache_control) = HeaderValue::from_str(&cache_control) {
+            headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+    }
+    if policy.compressible {
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    if let Some(encoding) = encoding {
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.token()),
+        );
+    }
+
+    if validators.not_modified(request_headers) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    let mime_type = mime_guess::from_path(path).first_or_octet_stream();
+    let range = request_headers
+        .get(header::RANGE)
+        .filter(|_| validators.if_range_matches(request_headers))
+        .and_then(|range| range.to_str().ok());
+
+    range::body_response(content, len, mime_type.as_ref(), headers, range)
+}
+
+/// `.br`, `.zst` or `.gz` sibling of `path` the client accepts best, if there is one.
+/// Siblings must be regular files, so links can't lead outside a [`StaticDir`]'s root.
+async fn precompressed_sibling(
+    path: &Path,
+    request_headers: &HeaderMap,
+) -> Option<(ContentEncoding, PathBuf)> {
+    let mut available = Vec::new();
+    for encoding in ContentEncoding::PRECOMPRESSED {
+        if ContentEncoding::negotiate(request_headers, &[encoding]).is_none() {
+            continue;
+        }
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(".");
+        sibling.push(encoding.extension());
+        let sibling = PathBuf::from(sibling);
+        if async_fs::symlink_metadata(&sibling)
+            .await
+            .is_ok_and(|metadata| metadata.is_file())
+        {
+            available.push((encoding, sibling));
+        }
+    }
+
+    let encodings: Vec<_> = available.iter().map(|(encoding, _)| *encoding).collect();
+    let encoding = ContentEncoding::negotiate(request_headers, &encodings)?;
+    available
+        .into_iter()
+        .find(|(available, _)| *available == encoding)
+}
+
+/// Serve the file at `file_path`, relative to the working directory.
+///
+/// Paths that could leave that directory are answered with 404, see
+/// [`normalize_path`](crate::utils::normalize_path).
 pub async fn serve_file(file_path: &str) -> Response {
-    let safe_path = crate::utils::sanitize_path(file_path);
+    serve_file_for(file_path, &HeaderMap::new()).await
+}
+
+/// Like [`serve_file`], answering conditional requests (`If-None-Match`, `If-Modified-Since`)
+/// with `304 Not Modified` using the request's `headers`
+pub async fn serve_file_for(file_path: &str, headers: &HeaderMap) -> Response {
+    let Some(safe_path) = crate::utils::normalize_path(file_path) else {
+        bevy_log::info!("Rejected unsafe file path: {}", file_path);
+        return not_found_response();
+    };
+
+    match file_response(&safe_path, headers).await {
+        Some(response) => response,
+        None => {
+            bevy_log::info!("File not found: {}", safe_path.display());
+            not_found_response()
+        }
+    }
+}
 
-    match fs::read(&safe_path) {
-        Ok(contents) => {
-            let mut headers = HeaderMap::new();
+fn not_found_response() -> Response {
+    // Try to get custom 404 response
+    match AsyncWorld
+        .resource::<HttpErrorResponses>()
+        .get(|responses| responses.create_response(StatusCode::NOT_FOUND))
+    {
+        Ok(response) => response,
+        Err(_) => {
+            error!("Failed to create 404 response, using default");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Service temporarily unavailable",
+            )
+                .into_response()
+        }
+    }
+}
 
-            let mime_type = mime_guess::from_path(&safe_path)
-                .first_or_octet_stream()
-                .to_string();
+/// How a [`StaticDir`] treats symbolic links below its root
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Follow links whose target is inside the root
+    #[default]
+    WithinRoot,
+    /// Follow every link, wherever it points
+    Follow,
+    /// Never serve a path that goes through a link
+    Deny,
+}
 
-            headers.insert(header::CONTENT_TYPE, mime_type.parse().unwrap());
+/// Directory served below a mount path, see
+/// [`WebServerAppExt::port_static`](crate::WebServerAppExt::port_static).
+///
+/// Requests only ever resolve to files inside the root. A relative root is taken relative to
+/// the working directory when the `StaticDir` is created, like other paths the app opens;
+/// pass an absolute path, e.g. one built from [`std::env::current_exe`], to serve files
+/// shipped next to the binary wherever it is launched from. Directories compiled into the
+/// binary with [`embed_dir!`](crate::embed_dir) are served through [`StaticDir::embedded`].
+#[derive(Clone, Debug)]
+pub struct StaticDir {
+    root: PathBuf,
+    /// `root` with links resolved, once it exists
+    canonical_root: Arc<OnceLock<PathBuf>>,
+    index_files: Vec<String>,
+    symlinks: SymlinkPolicy,
+    embedded: Option<EmbeddedDir>,
+}
 
-            // Add cache control for static assets
-            if WebStaticFileExtensions::is_static_asset(&safe_path).await {
-                headers.insert(
-                    header::CACHE_CONTROL,
-                    "public, max-age=3600".parse().unwrap(),
-                );
+impl StaticDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        // Fixed now, so changing the working directory later doesn't move the root
+        let root = std::path::absolute(&root).unwrap_or(root);
+        let canonical_root = OnceLock::new();
+        match root.canonicalize() {
+            Ok(canonical) => {
+                let _ = canonical_root.set(canonical);
             }
+            Err(err) => warn!(
+                "Static file root {} is unavailable, serving 404 until it exists: {}",
+                root.display(),
+                err
+            ),
+        }
 
-            (headers, contents).into_response()
+        Self {
+            root,
+            canonical_root: Arc::new(canonical_root),
+            index_files: vec!["index.html".to_string()],
+            symlinks: SymlinkPolicy::default(),
+            embedded: None,
         }
-        Err(_) => {
-            bevy_log::info!("File not found: {}", safe_path);
+    }
 
-            // Try to get custom 404 response
-            match AsyncWorld
-                .resource::<HttpErrorResponses>()
-                .get(|responses| responses.create_response(StatusCode::NOT_FOUND))
+    /// Serve files compiled into the binary, or their source directory when
+    /// [`EmbeddedDir::reads_from_disk`] is set
+    pub fn embedded(dir: EmbeddedDir) -> Self {
+        if dir.reads_from_disk() {
+            return Self::new(dir.source());
+        }
+        // The source directory usually doesn't exist where the binary is shipped
+        Self {
+            root: dir.source().to_path_buf(),
+            canonical_root: Default::default(),
+            index_files: vec!["index.html".to_string()],
+            symlinks: SymlinkPolicy::default(),
+            embedded: Some(dir),
+        }
+    }
+
+    /// Files served for requests to a directory, tried in order. Defaults to `index.html`.
+    pub fn with_index_files<I, S>(mut self, index_files: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.index_files = index_files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_symlink_policy(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Directory files are served from, the source directory of an embedded one
+    pub fn root(&self) -> &Path {
+        self.canonical_root.get().unwrap_or(&self.root)
+    }
+
+    /// Canonical root, resolved again on each call until the directory exists
+    async fn canonical_root(&self) -> Option<&Path> {
+        if let Some(root) = self.canonical_root.get() {
+            return Some(root);
+        }
+        let canonical = async_fs::canonicalize(&self.root).await.ok()?;
+        Some(self.canonical_root.get_or_init(|| canonical))
+    }
+
+    /// File or directory `relative` refers to, if it exists and may be served
+    pub async fn resolve(&self, relative: &str) -> Option<PathBuf> {
+        let root = self.canonical_root().await?;
+        let mut path = root.to_path_buf();
+        for component in crate::utils::normalize_path(relative)?.components() {
+            path.push(component);
+
+            if self.symlinks == SymlinkPolicy::Deny
+                && async_fs::symlink_metadata(&path)
+                    .await
+                    .is_ok_and(|metadata| metadata.is_symlink())
             {
-                Ok(response) => response,
-                Err(_) => {
-                    error!("Failed to create 404 response, using default");
-                    (
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        "Service temporarily unavailable",
-                    )
-                        .into_response()
+                return None;
+            }
+        }
+
+        let canonical = async_fs::canonicalize(&path).await.ok()?;
+        match self.symlinks {
+            SymlinkPolicy::WithinRoot | SymlinkPolicy::Deny => {
+                canonical.starts_with(root).then_some(canonical)
+            }
+            SymlinkPolicy::Follow => Some(canonical),
+        }
+    }
+
+    /// Answer a request for `relative`, the part of `uri` below the mount path
+    async fn serve(&self, uri: &Uri, headers: &HeaderMap, relative: &str) -> Response {
+        if let Some(embedded) = &self.embedded {
+            return self.serve_embedded(embedded, uri, headers, relative);
+        }
+        let Some(mut path) = self.resolve(relative).await else {
+            return HttpErrorResponses::respond(StatusCode::NOT_FOUND);
+        };
+
+        let metadata = async_fs::metadata(&path).await;
+        if metadata.is_ok_and(|metadata| metadata.is_dir()) {
+            if !uri.path().ends_with('/') {
+                return Self::redirect_to_dir(uri);
+            }
+            let mut index = None;
+            for name in &self.index_files {
+                if let Some(file) = self.resolve(&format!("{relative}/{name}")).await {
+                    if async_fs::metadata(&file)
+                        .await
+                        .is_ok_and(|metadata| metadata.is_file())
+                    {
+                        index = Some(file);
+                        break;
+                    }
                 }
             }
+            match index {
+                Some(index) => path = index,
+                None => return HttpErrorResponses::respond(StatusCode::NOT_FOUND),
+            }
+        }
+
+        match file_response(&path, headers).await {
+            Some(response) => response,
+            None => HttpErrorResponses::respond(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// Like [`serve`](Self::serve), from the files compiled into the binary
+    fn serve_embedded(
+        &self,
+        embedded: &EmbeddedDir,
+        uri: &Uri,
+        headers: &HeaderMap,
+        relative: &str,
+    ) -> Response {
+        if let Some(file) = embedded.get(relative) {
+            return embedded.response(file, headers);
+        }
+        if !embedded.is_dir(relative) {
+            return HttpErrorResponses::respond(StatusCode::NOT_FOUND);
+        }
+        if !uri.path().ends_with('/') {
+            return Self::redirect_to_dir(uri);
+        }
+        let index = self
+            .index_files
+            .iter()
+            .find_map(|index| embedded.get(&format!("{relative}/{index}")));
+        match index {
+            Some(index) => embedded.response(index, headers),
+            None => HttpErrorResponses::respond(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// Redirect a request for a directory to its path with a trailing slash, which
+    /// relative links in the index page need
+    fn redirect_to_dir(uri: &Uri) -> Response {
+        let location = match uri.query() {
+            Some(query) => format!("{}/?{}", uri.path(), query),
+            None => format!("{}/", uri.path()),
+        };
+        Redirect::permanent(&location).into_response()
+    }
+
+    /// Routes serving this directory below `mount`
+    pub(crate) fn router(self, mount: &str) -> Router {
+        let dir = Arc::new(self);
+        let mount = mount.trim_end_matches('/');
+
+        let index_dir = dir.clone();
+        let router = Router::new()
+            .route(
+                &format!("{mount}/"),
+                get(move |uri: Uri, headers: HeaderMap| async move {
+                    index_dir.serve(&uri, &headers, "").await
+                }),
+            )
+            .route(
+                &format!("{mount}/{{*path}}"),
+                get(
+                    move |uri: Uri, headers: HeaderMap, UriPath(path): UriPath<String>| async move {
+                        dir.serve(&uri, &headers, &path).await
+                    },
+                ),
+            );
+
+        if mount.is_empty() {
+            return router;
         }
+        let location = format!("{mount}/");
+        router.route(
+            mount,
+            get(move || async move { Redirect::permanent(&location) }),
+        )
+    }
+}
+
+impl<P: Into<PathBuf>> From<P> for StaticDir {
+    fn from(root: P) -> Self {
+        Self::new(root)
+    }
+}
+
+impl From<EmbeddedDir> for StaticDir {
+    fn from(dir: EmbeddedDir) -> Self {
+        Self::embedded(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_io::block_on;
+
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("webgate-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("public/docs")).unwrap();
+        fs::write(root.join("public/app.js"), "app").unwrap();
+        fs::write(root.join("public/docs/index.html"), "docs").unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_resolve_stays_in_root() {
+        let root = test_root("resolve");
+        let dir = StaticDir::new(root.join("public"));
+
+        assert!(block_on(dir.resolve("app.js")).is_some());
+        assert!(block_on(dir.resolve("./docs//index.html")).is_some());
+        assert!(block_on(dir.resolve("docs")).is_some_and(|path| path.is_dir()));
+        assert!(block_on(dir.resolve("../secret.txt")).is_none());
+        assert!(block_on(dir.resolve("docs/../../secret.txt")).is_none());
+        assert!(block_on(dir.resolve("missing.js")).is_none());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_root_created_later() {
+        let root = std::env::temp_dir().join(format!("webgate-later-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dir = StaticDir::new(&root);
+        assert!(block_on(dir.resolve("app.js")).is_none());
+
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("app.js"), "app").unwrap();
+        assert!(block_on(dir.resolve("app.js")).is_some());
+        assert!(block_on(dir.resolve("../app.js")).is_none());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_relative_root_uses_working_directory() {
+        let dir = StaticDir::new("webgate-relative-root");
+        assert_eq!(
+            dir.root(),
+            std::env::current_dir()
+                .unwrap()
+                .join("webgate-relative-root")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy() {
+        let root = test_root("symlinks");
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("public/outside.txt"))
+            .unwrap();
+        std::os::unix::fs::symlink(root.join("public/app.js"), root.join("public/inside.js"))
+            .unwrap();
+
+        let dir = StaticDir::new(root.join("public"));
+        assert!(block_on(dir.resolve("outside.txt")).is_none());
+        assert!(block_on(dir.resolve("inside.js")).is_some());
+
+        let dir = dir.with_symlink_policy(SymlinkPolicy::Deny);
+        assert!(block_on(dir.resolve("inside.js")).is_none());
+
+        let dir = dir.with_symlink_policy(SymlinkPolicy::Follow);
+        assert!(block_on(dir.resolve("outside.txt")).is_some());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_cache_control_per_extension() {
+        let mut extensions = WebStaticFileExtensions::default();
+        extensions
+            .set_cache_control("html", "no-cache")
+            .set_cache_control("woff2", "public, max-age=31536000, immutable");
+
+        assert_eq!(extensions.cache_control("css"), Some(DEFAULT_CACHE_CONTROL));
+        assert_eq!(extensions.cache_control("html"), Some("no-cache"));
+        assert_eq!(
+            extensions.cache_control("woff2"),
+            Some("public, max-age=31536000, immutable")
+        );
+        assert_eq!(extensions.cache_control("json"), None);
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        let root = test_root("conditional");
+        let path = root.join("public/app.js");
+        let respond = |request_headers: &HeaderMap| {
+            async_io::block_on(file_response(&path, request_headers)).unwrap()
+        };
+
+        let response = respond(&HeaderMap::new());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            DEFAULT_CACHE_CONTROL
+        );
+        let etag = response.headers()[header::ETAG].clone();
+        let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let response = respond(&request_headers);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        request_headers.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+        assert_eq!(respond(&request_headers).status(), StatusCode::OK);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_MODIFIED_SINCE, last_modified);
+        assert_eq!(respond(&request_headers).status(), StatusCode::NOT_MODIFIED);
+
+        request_headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Thu, 01 Jan 1970 00:00:00 GMT"),
+        );
+        assert_eq!(respond(&request_headers).status(), StatusCode::OK);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-0"));
+        request_headers.insert(header::IF_RANGE, etag);
+        assert_eq!(
+            respond(&request_headers).status(),
+            StatusCode::PARTIAL_CONTENT
+        );
+        request_headers.insert(header::IF_RANGE, HeaderValue::from_static("\"stale\""));
+        assert_eq!(respond(&request_headers).status(), StatusCode::OK);
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_precompressed_siblings() {
+        let root = test_root("precompressed");
+        let path = root.join("public/app.js");
+        fs::write(root.join("public/app.js.gz"), "gzipped").unwrap();
+        fs::write(root.join("public/app.js.br"), "brotli").unwrap();
+
+        let respond = |accept_encoding: &'static str| {
+            let mut request_headers = HeaderMap::new();
+            request_headers.insert(
+                header::ACCEPT_ENCODING,
+                HeaderValue::from_static(accept_encoding),
+            );
+            let response = async_io::block_on(file_response(&path, &request_headers)).unwrap();
+            let (parts, body) = response.into_parts();
+            let body = async_io::block_on(axum::body::to_bytes(body, usize::MAX)).unwrap();
+            (parts.headers, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        let (headers, body) = respond("gzip, br");
+        assert_eq!(headers[header::CONTENT_ENCODING], "br");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(headers[header::VARY], "accept-encoding");
+        assert_eq!(body, "brotli");
+
+        let (headers, body) = respond("gzip;q=1, br;q=0.5");
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(body, "gzipped");
+
+        let (headers, body) = respond("identity");
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+        assert_eq!(body, "app");
+        let _ = fs::remove_dir_all(root);
     }
 }
@@ -0,0 +1,242 @@
+use super::{content_response, range::Content, FilePolicy, Validators};
+use crate::compression::ContentEncoding;
+use axum::{http::HeaderMap, response::Response};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// File compiled into the binary by [`embed_dir!`](crate::embed_dir)
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedFile {
+    path: &'static str,
+    contents: &'static [u8],
+    etag: &'static str,
+    modified: u64,
+}
+
+impl EmbeddedFile {
+    #[doc(hidden)]
+    pub const fn new(
+        path: &'static str,
+        contents: &'static [u8],
+        etag: &'static str,
+        modified: u64,
+    ) -> Self {
+        Self {
+            path,
+            contents,
+            etag,
+            modified,
+        }
+    }
+
+    /// `/`-separated path below the embedded directory
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    pub fn contents(&self) -> &'static [u8] {
+        self.contents
+    }
+
+    fn validators(&self) -> Validators {
+        Validators {
+            etag: self.etag.to_string(),
+            modified: (self.modified > 0).then(|| UNIX_EPOCH + Duration::from_secs(self.modified)),
+        }
+    }
+}
+
+/// Directory compiled into the binary by [`embed_dir!`](crate::embed_dir), served like a
+/// directory on disk through [`StaticDir`](super::StaticDir).
+///
+/// ETags come from the file contents and `Last-Modified` from the build-time modification
+/// date, so caches stay valid across builds that don't change a file.
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedDir {
+    source: &'static str,
+    /// Sorted by path
+    files: &'static [EmbeddedFile],
+    disk_in_debug: bool,
+}
+
+impl EmbeddedDir {
+    #[doc(hidden)]
+    pub const fn new(source: &'static str, files: &'static [EmbeddedFile]) -> Self {
+        Self {
+            source,
+            files,
+            disk_in_debug: false,
+        }
+    }
+
+    /// In debug builds, serve the source directory from disk instead of the embedded copy
+    /// so edits show up without recompiling. Release builds always use the embedded files.
+    pub fn with_disk_in_debug(mut self, enabled: bool) -> Self {
+        self.disk_in_debug = enabled;
+        self
+    }
+
+    /// Directory the files were embedded from, at build time
+    pub fn source(&self) -> &'static Path {
+        Path::new(self.source)
+    }
+
+    /// Whether the files are read from [`source`](Self::source) rather than the binary
+    pub fn reads_from_disk(&self) -> bool {
+        cfg!(debug_assertions) && self.disk_in_debug && self.source().is_dir()
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &'static EmbeddedFile> {
+        self.files.iter()
+    }
+
+    /// Embedded file `relative` refers to, after [`normalize_path`](crate::utils::normalize_path)
+    pub fn get(&self, relative: &str) -> Option<&'static EmbeddedFile> {
+        self.get_normalized(&Self::normalize(relative)?)
+    }
+
+    fn get_normalized(&self, path: &str) -> Option<&'static EmbeddedFile> {
+        let files = self.files;
+        files
+            .binary_search_by(|file| file.path.cmp(path))
+            .ok()
+            .map(|index| &files[index])
+    }
+
+    /// Whether `relative` refers to a directory holding embedded files
+    pub(super) fn is_dir(&self, relative: &str) -> bool {
+        let Some(path) = Self::normalize(relative) else {
+            return false;
+        };
+        path.is_empty()
+            || self.files.iter().any(|file| {
+                file.path
+                    .strip_prefix(path.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+
+    fn normalize(relative: &str) -> Option<String> {
+        let path = crate::utils::normalize_path(relative)?;
+        let segments: Option<Vec<&str>> = path
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect();
+        Some(segments?.join("/"))
+    }
+
+    /// `.br`, `.zst` or `.gz` sibling of `file` the client accepts best, if one is embedded
+    fn precompressed_sibling(
+        &self,
+        file: &EmbeddedFile,
+        request_headers: &HeaderMap,
+    ) -> Option<(ContentEncoding, &'static EmbeddedFile)> {
+        let available: Vec<_> = ContentEncoding::PRECOMPRESSED
+            .into_iter()
+            .filter_map(|encoding| {
+                let sibling = format!("{}.{}", file.path, encoding.extension());
+                Some((encoding, self.get_normalized(&sibling)?))
+            })
+            .collect();
+        let encodings: Vec<_> = available.iter().map(|(encoding, _)| *encoding).collect();
+        let encoding = ContentEncoding::negotiate(request_headers, &encodings)?;
+        available
+            .into_iter()
+            .find(|(available, _)| *available == encoding)
+    }
+
+    /// Response with `file`, with the same headers and conditional handling as files on disk
+    pub(super) fn response(
+        &self,
+        file: &'static EmbeddedFile,
+        request_headers: &HeaderMap,
+    ) -> Response {
+        let path = Path::new(file.path);
+        let policy = FilePolicy::for_path(path);
+        let precompressed = match policy.compressible {
+            true => self.precompressed_sibling(file, request_headers),
+            false => None,
+        };
+        let (encoding, served) = match precompressed {
+            Some((encoding, sibling)) => (Some(encoding), sibling),
+            None => (None, file),
+        };
+
+        content_response(
+            path,
+            Content::Embedded(served.contents),
+            served.contents.len() as u64,
+            &served.validators(),
+            policy,
+            encoding,
+            request_headers,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, HeaderValue, StatusCode};
+
+    const FILES: &[EmbeddedFile] = &[
+        EmbeddedFile::new("app.js", b"app", "\"3-1\"", 1_700_000_000),
+        EmbeddedFile::new("app.js.gz", b"gzipped", "\"7-2\"", 1_700_000_000),
+        EmbeddedFile::new("docs/index.html", b"docs", "\"4-3\"", 0),
+    ];
+
+    fn respond(path: &str, request_headers: &HeaderMap) -> (StatusCode, HeaderMap, String) {
+        let dir = EmbeddedDir::new("/nonexistent", FILES);
+        let response = dir.response(dir.get(path).unwrap(), request_headers);
+        let (parts, body) = response.into_parts();
+        let body = async_io::block_on(axum::body::to_bytes(body, usize::MAX)).unwrap();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_lookup() {
+        let dir = EmbeddedDir::new("/nonexistent", FILES);
+        assert_eq!(dir.get("/./docs//index.html").unwrap().contents(), b"docs");
+        assert!(dir.get("docs/../app.js").is_none());
+        assert!(dir.get("missing.js").is_none());
+        assert!(dir.is_dir("docs"));
+        assert!(dir.is_dir(""));
+        assert!(!dir.is_dir("doc"));
+        assert!(!dir.is_dir("app.js"));
+        assert!(!dir.with_disk_in_debug(true).reads_from_disk());
+    }
+
+    #[test]
+    fn test_embedded_responses() {
+        let (status, headers, body) = respond("app.js", &HeaderMap::new());
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(headers[header::ETAG], "\"3-1\"");
+        assert_eq!(
+            headers[header::LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+        assert!(headers.contains_key(header::CACHE_CONTROL));
+        assert_eq!(body, "app");
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"3-1\""));
+        assert_eq!(
+            respond("app.js", &request_headers).0,
+            StatusCode::NOT_MODIFIED
+        );
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let (_, headers, body) = respond("app.js", &request_headers);
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(body, "gzipped");
+
+        let (_, headers, _) = respond("docs/index.html", &HeaderMap::new());
+        assert!(!headers.contains_key(header::LAST_MODIFIED));
+    }
+}
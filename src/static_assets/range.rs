@@ -0,0 +1,348 @@
+use async_fs::File;
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::{AsyncReadExt, AsyncSeekExt};
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, RandomState};
+use std::io::SeekFrom;
+use std::ops::Range;
+
+use crate::HttpErrorResponses;
+
+/// Bytes read from disk at a time, bounding the memory a response holds
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Ranges served in one response; requests for more get the whole file
+const MAX_RANGES: usize = 16;
+
+/// Byte ranges of a file with `len` bytes a `Range` header asks for, sorted and merged.
+///
+/// `None` if the header can't be used and the whole file should be sent, an empty list
+/// if none of the ranges overlap the file.
+fn parse_ranges(range: &str, len: u64) -> Option<Vec<Range<u64>>> {
+    let specs = range.trim().strip_prefix("bytes=")?;
+
+    let mut ranges = Vec::new();
+    for spec in specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+    {
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        let range = if start.is_empty() {
+            // Suffix range: the last `end` bytes
+            let suffix = end.parse::<u64>().ok()?;
+            len.saturating_sub(suffix)..len
+        } else {
+            let start = start.parse::<u64>().ok()?;
+            let end = match end {
+                "" => len,
+                end => end.parse::<u64>().ok()?.checked_add(1)?.min(len),
+            };
+            if end <= start && start < len {
+                return None;
+            }
+            start..end
+        };
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+    if ranges.len() > MAX_RANGES {
+        return None;
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Some(merged)
+}
+
+fn content_range(range: &Range<u64>, len: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
+/// Piece of a response body
+enum BodyPart {
+    Bytes(Bytes),
+    File(Range<u64>),
+}
+
+impl BodyPart {
+    fn len(&self) -> u64 {
+        match self {
+            Self::Bytes(bytes) => bytes.len() as u64,
+            Self::File(range) => range.end - range.start,
+        }
+    }
+}
+
+/// Where the bytes of a response body come from
+pub(super) enum Content {
+    File(File),
+    /// Compiled into the binary, sent without copying
+    Embedded(&'static [u8]),
+}
+
+/// Reads the parts of a response body from its content without blocking the executor
+struct ChunkReader {
+    content: Content,
+    /// Offset the next read starts at, saving a seek when ranges are read in order
+    position: u64,
+    parts: VecDeque<BodyPart>,
+}
+
+impl ChunkReader {
+    /// Read the next chunk, or `None` once every part is sent
+    async fn next_chunk(&mut self) -> Option<std::io::Result<Bytes>> {
+        match self.parts.pop_front()? {
+            BodyPart::Bytes(bytes) => Some(Ok(bytes)),
+            BodyPart::File(range) => Some(self.read_chunk(range).await),
+        }
+    }
+
+    async fn read_chunk(&mut self, range: Range<u64>) -> std::io::Result<Bytes> {
+        let file = match &mut self.content {
+            Content::File(file) => file,
+            Content::Embedded(bytes) => {
+                return Ok(Bytes::from_static(
+                    &bytes[range.start as usize..range.end as usize],
+                ))
+            }
+        };
+        if self.position != range.start {
+            file.seek(SeekFrom::Start(range.start)).await?;
+        }
+        let len = (range.end - range.start).min(CHUNK_SIZE);
+        let mut chunk = vec![0; len as usize];
+        file.read_exact(&mut chunk).await?;
+
+        self.position = range.start + len;
+        if self.position < range.end {
+            self.parts
+                .push_front(BodyPart::File(self.position..range.end));
+        }
+        Ok(chunk.into())
+    }
+}
+
+/// Body streaming `parts` of `content` chunk by chunk, so only one chunk is in memory at a time
+fn stream_body(content: Content, parts: VecDeque<BodyPart>) -> Body {
+    let reader = ChunkReader {
+        content,
+        position: 0,
+        parts,
+    };
+    let chunks = futures_util::stream::unfold(reader, |mut reader| async move {
+        let chunk = reader.next_chunk().await?;
+        Some((chunk, reader))
+    });
+    Body::from_stream(chunks)
+}
+
+/// Response for the `len` bytes of `content`, or the parts of it `range` asks for.
+///
+/// `headers` already holds the validators and caching headers of the file.
+pub(super) fn body_response(
+    content: Content,
+    len: u64,
+    content_type: &str,
+    mut headers: HeaderMap,
+    range: Option<&str>,
+) -> Response {
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    let ranges = range.and_then(|range| parse_ranges(range, len));
+
+    let (status, parts) = match ranges.as_deref() {
+        None => {
+            headers.insert(header::CONTENT_TYPE, header_value(content_type));
+            (StatusCode::OK, VecDeque::from([BodyPart::File(0..len)]))
+        }
+        Some([]) => {
+            let mut response = HttpErrorResponses::respond(StatusCode::RANGE_NOT_SATISFIABLE);
+            response.headers_mut().insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes */{len}")),
+            );
+            return response;
+        }
+        Some([range]) => {
+            headers.insert(header::CONTENT_TYPE, header_value(content_type));
+            headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&content_range(range, len)),
+            );
+            (
+                StatusCode::PARTIAL_CONTENT,
+                VecDeque::from([BodyPart::File(range.clone())]),
+            )
+        }
+        Some(ranges) => {
+            let boundary = format!("webgate-{:016x}", RandomState::new().hash_one(len));
+            let mut parts = VecDeque::with_capacity(ranges.len() * 2 + 1);
+            for range in ranges {
+                let part_headers = format!(
+                    "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+                    content_range(range, len)
+                );
+                parts.push_back(BodyPart::Bytes(part_headers.into()));
+                parts.push_back(BodyPart::File(range.clone()));
+            }
+            parts.push_back(BodyPart::Bytes(format!("\r\n--{boundary}--\r\n").into()));
+
+            headers.insert(
+                header::CONTENT_TYPE,
+                header_value(&format!("multipart/byteranges; boundary={boundary}")),
+            );
+            (StatusCode::PARTIAL_CONTENT, parts)
+        }
+    };
+
+    let body_len: u64 = parts.iter().map(BodyPart::len).sum();
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body_len));
+    (status, headers, stream_body(content, parts)).into_response()
+}
+
+/// Header value of a content type or range, which only hold visible ASCII
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value)
+        .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(range: &str) -> Option<Vec<(u64, u64)>> {
+        parse_ranges(range, 1000).map(|ranges| {
+            ranges
+                .into_iter()
+                .map(|range| (range.start, range.end))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(parse("bytes=0-99"), Some(vec![(0, 100)]));
+        assert_eq!(parse("bytes=900-"), Some(vec![(900, 1000)]));
+        assert_eq!(parse("bytes=-100"), Some(vec![(900, 1000)]));
+        assert_eq!(parse("bytes=990-2000"), Some(vec![(990, 1000)]));
+        assert_eq!(
+            parse("bytes=500-599, 0-99,50-149"),
+            Some(vec![(0, 150), (500, 600)])
+        );
+        assert_eq!(parse("bytes=1000-"), Some(vec![]));
+        assert_eq!(parse("bytes=-0"), Some(vec![]));
+        assert_eq!(parse("bytes=99-0"), None);
+        assert_eq!(parse("items=0-1"), None);
+        assert_eq!(parse("bytes=a-b"), None);
+    }
+
+    fn respond(range: &str) -> (StatusCode, HeaderMap, String) {
+        let path = std::env::temp_dir().join(format!("webgate-range-{}.txt", std::process::id()));
+        std::fs::write(&path, "0123456789").unwrap();
+        let file = async_io::block_on(File::open(&path)).unwrap();
+        let response = body_response(
+            Content::File(file),
+            10,
+            "text/plain",
+            HeaderMap::new(),
+            Some(range),
+        );
+        let (parts, body) = response.into_parts();
+        let body = async_io::block_on(axum::body::to_bytes(body, usize::MAX)).unwrap();
+        let _ = std::fs::remove_file(path);
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_range_responses() {
+        let (status, headers, body) = respond("bytes=2-4");
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 2-4/10");
+        assert_eq!(headers[header::CONTENT_LENGTH], "3");
+        assert_eq!(body, "234");
+
+        let (status, headers, body) = respond("bytes=0-1,-2");
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        let content_type = headers[header::CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "\r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+                 \r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+                 \r\n--{boundary}--\r\n"
+            )
+        );
+        assert_eq!(headers[header::CONTENT_LENGTH], body.len().to_string());
+
+        let (status, headers, _) = respond("bytes=20-30");
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */10");
+
+        let (status, headers, body) = respond("lines=1-2");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body, "0123456789");
+    }
+
+    #[test]
+    fn test_large_files_stream_in_chunks() {
+        use futures_util::StreamExt;
+
+        let path = std::env::temp_dir().join(format!("webgate-chunks-{}.bin", std::process::id()));
+        let contents: Vec<u8> = (0..300_000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let file = async_io::block_on(File::open(&path)).unwrap();
+        let response = body_response(
+            Content::File(file),
+            contents.len() as u64,
+            "application/octet-stream",
+            HeaderMap::new(),
+            Some("bytes=1000-"),
+        );
+        let chunks: Vec<Bytes> = async_io::block_on(
+            response
+                .into_body()
+                .into_data_stream()
+                .map(Result::unwrap)
+                .collect(),
+        );
+        let _ = std::fs::remove_file(path);
+
+        assert!(chunks.iter().all(|chunk| chunk.len() as u64 <= CHUNK_SIZE));
+        assert_eq!(chunks.concat(), contents[1000..]);
+    }
+
+    #[test]
+    fn test_embedded_ranges() {
+        let response = body_response(
+            Content::Embedded(b"0123456789"),
+            10,
+            "text/plain",
+            HeaderMap::new(),
+            Some("bytes=-3"),
+        );
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let body = async_io::block_on(axum::body::to_bytes(response.into_body(), usize::MAX));
+        assert_eq!(body.unwrap(), "789");
+    }
+}
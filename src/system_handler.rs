@@ -0,0 +1,265 @@
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, FromRequest, FromRequestParts, RawPathParams, Request},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::{on, MethodFilter, MethodRouter},
+};
+use bevy_app::App;
+use bevy_ecs::{
+    prelude::*,
+    schedule::{InternedScheduleLabel, ScheduleLabel},
+    system::SystemId,
+};
+use bevy_log::error;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use crate::{HttpErrorResponses, RequestContext};
+
+/// Request handed to a Bevy system registered as a route handler.
+///
+/// ```rust,ignore
+/// fn score(In(request): In<WebRequest>, scores: Query<(&Player, &Score)>) -> String {
+///     let name = request.param("name").unwrap_or_default();
+///     scores
+///         .iter()
+///         .find(|(player, _)| player.0 == name)
+///         .map(|(_, score)| score.0.to_string())
+///         .unwrap_or_default()
+/// }
+///
+/// app.route_system("/score/{name}", MethodFilter::GET, score);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WebRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    /// Path parameters captured by the route, in order
+    pub params: Vec<(String, String)>,
+    pub body: Bytes,
+    pub peer: Option<SocketAddr>,
+    pub context: RequestContext,
+}
+
+impl WebRequest {
+    /// Value of the path parameter `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+impl<S: Send + Sync> FromRequest<S> for WebRequest {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, _state: &S) -> Result<Self, Response> {
+        let (mut parts, body) = request.into_parts();
+
+        let params = RawPathParams::from_request_parts(&mut parts, &())
+            .await
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| *peer);
+        let Ok(context) = RequestContext::from_request_parts(&mut parts, &()).await;
+
+        let method = parts.method.clone();
+        let uri = parts.uri.clone();
+        let headers = parts.headers.clone();
+        // Goes through axum's body extractor so the default body size limit applies
+        let body = Bytes::from_request(Request::from_parts(parts, body), &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(Self {
+            method,
+            uri,
+            headers,
+            params,
+            body,
+            peer,
+            context,
+        })
+    }
+}
+
+pub(crate) struct PendingWebRequest {
+    system: SystemId<In<WebRequest>, Response>,
+    request: WebRequest,
+    responder: async_channel::Sender<Response>,
+}
+
+/// Requests waiting for their system, one queue per schedule that runs system handlers
+#[derive(Default, Resource)]
+pub(crate) struct SystemHandlerQueues {
+    queues: HashMap<InternedScheduleLabel, async_channel::Sender<PendingWebRequest>>,
+}
+
+/// Register `system` as a handler run in `schedule` and build the route dispatching to it
+pub(crate) fn system_method_router<R, M>(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+    method: MethodFilter,
+    system: impl IntoSystem<In<WebRequest>, R, M> + 'static,
+) -> MethodRouter<()>
+where
+    R: IntoResponse + 'static,
+{
+    let schedule = schedule.intern();
+    let system = app
+        .world_mut()
+        .register_system(system.map(IntoResponse::into_response));
+
+    let existing = app
+        .world_mut()
+        .get_resource_or_init::<SystemHandlerQueues>()
+        .queues
+        .get(&schedule)
+        .cloned();
+    let queue = match existing {
+        Some(queue) => queue,
+        None => {
+            let (sender, receiver) = async_channel::unbounded();
+            app.world_mut()
+                .resource_mut::<SystemHandlerQueues>()
+                .queues
+                .insert(schedule, sender.clone());
+            app.add_systems(schedule, move |world: &mut World| {
+                run_queued_requests(world, &receiver)
+            });
+            sender
+        }
+    };
+
+    on(method, move |request: Request| {
+        let queue = queue.clone();
+        async move {
+            let request = match WebRequest::from_request(request, &()).await {
+                Ok(request) => request,
+                Err(rejection) => return rejection,
+            };
+
+            let (responder, response) = async_channel::bounded(1);
+            let pending = PendingWebRequest {
+                system,
+                request,
+                responder,
+            };
+            if queue.send(pending).await.is_err() {
+                return HttpErrorResponses::respond(StatusCode::SERVICE_UNAVAILABLE);
+            }
+
+            response
+                .recv()
+                .await
+                .unwrap_or_else(|_| HttpErrorResponses::respond(StatusCode::SERVICE_UNAVAILABLE))
+        }
+    })
+}
+
+/// Run the system of every request queued so far, in arrival order
+pub(crate) fn run_queued_requests(
+    world: &mut World,
+    receiver: &async_channel::Receiver<PendingWebRequest>,
+) {
+    while let Ok(pending) = receiver.try_recv() {
+        let response = {
+            let _entered = pending.request.context.span().clone().entered();
+            world
+                .run_system_with(pending.system, pending.request)
+                .unwrap_or_else(|err| {
+                    error!("Failed to run system handler: {}", err);
+                    world
+                        .get_resource::<HttpErrorResponses>()
+                        .cloned()
+                        .unwrap_or_default()
+                        .create_response(StatusCode::INTERNAL_SERVER_ERROR)
+                })
+        };
+        // The client may have gone away in the meantime
+        let _ = pending.responder.try_send(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use bevy_app::Update;
+
+    #[derive(Resource, Default)]
+    struct Hits(u32);
+
+    fn count_hits(In(request): In<WebRequest>, mut hits: ResMut<Hits>) -> String {
+        hits.0 += 1;
+        format!("{} {}", request.text().unwrap(), hits.0)
+    }
+
+    #[test]
+    fn test_queued_requests_run_in_order() {
+        let mut app = App::new();
+        app.init_resource::<Hits>();
+        let system = app
+            .world_mut()
+            .register_system(count_hits.map(IntoResponse::into_response));
+
+        let (sender, receiver) = async_channel::unbounded();
+        app.add_systems(Update, move |world: &mut World| {
+            run_queued_requests(world, &receiver)
+        });
+
+        let responses: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|body| {
+                let request = Request::new(Body::from(body));
+                let request =
+                    futures_util::FutureExt::now_or_never(WebRequest::from_request(request, &()))
+                        .unwrap()
+                        .unwrap();
+                let (responder, response) = async_channel::bounded(1);
+                sender
+                    .try_send(PendingWebRequest {
+                        system,
+                        request,
+                        responder,
+                    })
+                    .unwrap();
+                response
+            })
+            .collect();
+
+        app.update();
+
+        assert_eq!(app.world().resource::<Hits>().0, 2);
+        let bodies: Vec<_> = responses
+            .into_iter()
+            .map(|response| {
+                let body = response.try_recv().unwrap().into_body();
+                futures_util::FutureExt::now_or_never(axum::body::to_bytes(body, usize::MAX))
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(bodies, ["first 1", "second 2"]);
+    }
+}
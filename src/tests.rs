@@ -1,8 +1,11 @@
 use super::*;
 use async_io::Async;
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
 use axum::{response::Html, routing::get, Router};
 use bevy::prelude::*;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tower::ServiceExt;
 
 fn create_test_app() -> App {
     let mut app = App::new();
@@ -10,6 +13,17 @@ fn create_test_app() -> App {
     app
 }
 
+/// Send a request through a server's router, blocking until the whole body is read
+fn call(server: &WebServer, request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+    // File reads complete on another thread
+    async_io::block_on(async {
+        let response = server.service_router().oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, body)
+    })
+}
+
 #[test]
 fn test_web_servers_basic_operations() {
     let mut servers = WebServerManager::default();
@@ -339,3 +353,395 @@ fn test_exact_bind_replication() {
         "Direct async bind should succeed for available port"
     );
 }
+
+#[test]
+fn test_server_stats_report() {
+    let mut servers = WebServerManager::default();
+    servers
+        .add_server(WebServer::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            27080,
+            Router::new(),
+        ))
+        .unwrap();
+
+    let report = servers.server_status_report();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].0, 27080);
+
+    let report = servers.server_stats_report();
+    assert_eq!(report.len(), 1);
+    let (port, stats) = &report[0];
+    assert_eq!(*port, 27080);
+    assert_eq!(stats.total_requests, 0);
+    assert_eq!(stats.in_flight, 0);
+
+    assert_eq!(servers.server_stats(&27080), Some(stats.clone()));
+    assert!(servers.server_stats(&9999).is_none());
+}
+
+#[test]
+fn test_cloned_server_shares_stats() {
+    let server = WebServer::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 27081, Router::new());
+    let clone = server.clone();
+
+    call(&server, Request::get("/").body(Body::empty()).unwrap());
+    assert_eq!(server.stats().total_requests(), 1);
+    assert_eq!(clone.stats().total_requests(), 1);
+}
+
+#[test]
+fn test_port_access_log() {
+    let mut app = create_test_app();
+
+    app.port_access_log(
+        28080,
+        AccessLogConfig::new(AccessLogFormat::Combined).exclude_path("/health"),
+    );
+
+    let servers = app.world().get_resource::<WebServerManager>().unwrap();
+    let access_log = servers.get_server(&28080).unwrap().access_log().unwrap();
+    assert_eq!(access_log.format, AccessLogFormat::Combined);
+    assert_eq!(access_log.target, AccessLogTarget::Log);
+    assert_eq!(access_log.exclude_paths, vec!["/health".to_string()]);
+}
+
+#[test]
+fn test_default_error_fallbacks() {
+    let mut app = create_test_app();
+    app.port_route(29080, "/score", get(|| async { "42" }))
+        .port_route(29081, "/score", get(|| async { "42" }))
+        .port_method_not_allowed_fallback(29081, || async { "read only" });
+
+    let servers = app.world().resource::<WebServerManager>();
+    let request = |method: Method, path: &str| {
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (scores, overridden) = (
+        servers.get_server(&29080).unwrap(),
+        servers.get_server(&29081).unwrap(),
+    );
+
+    let (status, headers, _) = call(scores, request(Method::GET, "/missing"));
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+
+    let (status, headers, _) = call(scores, request(Method::POST, "/score"));
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    assert!(headers.contains_key(header::ALLOW));
+
+    let (status, _, _) = call(overridden, request(Method::POST, "/score"));
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = call(overridden, request(Method::GET, "/score"));
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[test]
+fn test_user_router_error_fallbacks() {
+    let router = Router::new()
+        .route("/score", get(|| async { "42" }))
+        .route(
+            "/player",
+            get(|| async { (StatusCode::NOT_FOUND, "no such player") }),
+        )
+        .route("/item", get(|| async { StatusCode::NOT_FOUND }));
+    let server = WebServer::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 29082, router);
+    let request = |path: &str, accept: &str| {
+        Request::get(path)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (status, headers, _) = call(&server, request("/missing", "text/html"));
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+    let (_, headers, _) = call(&server, request("/missing", "application/json"));
+    assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+
+    // 404s returned by handlers are left alone, also without a body
+    let (status, _, body) = call(&server, request("/player", "text/html"));
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "no such player");
+    let (status, headers, body) = call(&server, request("/item", "text/html"));
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(!headers.contains_key(header::CONTENT_TYPE));
+    assert!(body.is_empty());
+}
+
+#[test]
+fn test_negotiated_error_responses() {
+    let mut api_errors = HttpErrorResponses::default();
+    api_errors.set_problem(
+        StatusCode::NOT_FOUND,
+        ProblemDetails::new("No such endpoint").with_detail("See /docs for the API"),
+    );
+
+    let mut app = create_test_app();
+    app.port_route(29180, "/score", get(|| async { "42" }))
+        .port_route(29181, "/score", get(|| async { "42" }))
+        .port_error_responses(29181, api_errors);
+
+    let servers = app.world().resource::<WebServerManager>();
+    let missing = |port: WebPort, accept: &str| {
+        let request = Request::get("/missing")
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+        let (_, headers, body) = call(servers.get_server(&port).unwrap(), request);
+        (
+            headers[header::CONTENT_TYPE].clone(),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    };
+
+    let (content_type, body) = missing(29180, "application/json");
+    assert_eq!(content_type, "application/problem+json");
+    let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["status"], 404);
+    assert_eq!(problem["title"], "Not Found");
+
+    let (content_type, body) = missing(29180, "text/plain");
+    assert_eq!(content_type, "text/plain; charset=utf-8");
+    assert!(body.starts_with("404 Not Found"));
+
+    let (content_type, _) = missing(29180, "text/html");
+    assert_eq!(content_type, "text/html");
+
+    let (_, body) = missing(29181, "application/problem+json");
+    let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["title"], "No such endpoint");
+    assert_eq!(problem["detail"], "See /docs for the API");
+}
+
+#[test]
+fn test_templated_error_pages() {
+    let mut errors = HttpErrorResponses::default();
+    errors.set_default_template(ErrorTemplate::new(
+        "<p>{{code}} {{method}} {{path}} [{{request_id}}] {{detail}}</p>",
+    ));
+
+    let mut app = create_test_app();
+    app.port_route(
+        29182,
+        "/banned",
+        get(|| async {
+            HttpErrorResponses::respond_with_detail(StatusCode::FORBIDDEN, "Banned until <Friday>")
+        }),
+    )
+    .port_error_responses(29182, errors);
+
+    let server = app
+        .world()
+        .resource::<WebServerManager>()
+        .get_server(&29182)
+        .unwrap();
+    let page = |path: &str| {
+        let request = Request::get(path)
+            .header("x-request-id", "req-7")
+            .body(Body::empty())
+            .unwrap();
+        let (_, _, body) = call(server, request);
+        String::from_utf8(body.to_vec()).unwrap()
+    };
+
+    assert_eq!(page("/missing"), "<p>404 GET /missing [req-7] </p>");
+    assert_eq!(
+        page("/banned"),
+        "<p>403 GET /banned [req-7] Banned until &lt;Friday&gt;</p>"
+    );
+}
+
+#[test]
+fn test_static_mount() {
+    let root = std::env::temp_dir().join(format!("webgate-static-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("public/docs")).unwrap();
+    std::fs::write(root.join("public/style.css"), "body {}").unwrap();
+    std::fs::write(root.join("public/docs/index.html"), "docs").unwrap();
+    std::fs::write(root.join("public/100%.png"), "png").unwrap();
+    std::fs::write(root.join("private.txt"), "secret").unwrap();
+
+    let mut app = create_test_app();
+    app.port_static(29183, "/static", root.join("public"));
+
+    let server = app
+        .world()
+        .resource::<WebServerManager>()
+        .get_server(&29183)
+        .unwrap();
+    let fetch = |path: &str| call(server, Request::get(path).body(Body::empty()).unwrap());
+
+    let (status, headers, _) = fetch("/static/style.css");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/css");
+    assert_eq!(fetch("/static/docs/").0, StatusCode::OK);
+    let (status, headers, _) = fetch("/static/docs");
+    assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(headers[header::LOCATION], "/static/docs/");
+    assert_eq!(fetch("/static/../private.txt").0, StatusCode::NOT_FOUND);
+    assert_eq!(fetch("/static/%2e%2e/private.txt").0, StatusCode::NOT_FOUND);
+    assert_eq!(fetch("/static/missing.css").0, StatusCode::NOT_FOUND);
+    // Decoded once by the router, not again when resolving the file
+    let (status, _, body) = fetch("/static/100%25.png");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "png");
+
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_embedded_mount() {
+    let assets = crate::embed_dir!("examples/file_server_assets");
+    assert!(assets.get("index.html").is_some());
+    assert!(!assets.reads_from_disk());
+
+    let mut app = create_test_app();
+    app.port_static(29184, "/assets", assets);
+
+    let server = app
+        .world()
+        .resource::<WebServerManager>()
+        .get_server(&29184)
+        .unwrap();
+    let fetch = |path: &str| call(server, Request::get(path).body(Body::empty()).unwrap());
+
+    let (status, headers, body) = fetch("/assets/style.css");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/css");
+    assert!(headers.contains_key(header::ETAG));
+    assert_eq!(
+        body,
+        include_bytes!("../examples/file_server_assets/style.css").as_slice()
+    );
+
+    let (status, headers, _) = fetch("/assets/");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+    assert_eq!(fetch("/assets").0, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(fetch("/assets/../Cargo.toml").0, StatusCode::NOT_FOUND);
+    assert_eq!(fetch("/assets/missing.css").0, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_stopping_dedicated_server_cancels_handlers() {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Sets its flag once the handler future holding it is dropped
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let started = Arc::new(AtomicBool::new(false));
+    let dropped = Arc::new(AtomicBool::new(false));
+    let (handler_started, handler_dropped) = (started.clone(), dropped.clone());
+
+    let mut app = create_test_app();
+    app.add_plugins(BevyWebServerPlugin)
+        .port_route(
+            29186,
+            "/slow",
+            get(move || {
+                let (started, dropped) = (handler_started.clone(), handler_dropped.clone());
+                async move {
+                    let _guard = SetOnDrop(dropped);
+                    started.store(true, Ordering::SeqCst);
+                    std::future::pending::<()>().await
+                }
+            }),
+        )
+        .port_io_mode(29186, IoMode::Dedicated { threads: 1 });
+
+    let mut client = None;
+    for _ in 0..500 {
+        app.update();
+        if client.is_none() {
+            client = std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, 29186)).ok();
+            if let Some(client) = &mut client {
+                client
+                    .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .unwrap();
+            }
+        }
+        if started.load(Ordering::SeqCst) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    assert!(started.load(Ordering::SeqCst), "handler never started");
+    assert!(!dropped.load(Ordering::SeqCst));
+
+    app.world_mut()
+        .resource_mut::<WebServerManager>()
+        .get_server_mut(&29186)
+        .unwrap()
+        .stop();
+    // Cancelled tasks are dropped the next time the executor runs
+    app.update();
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_dedicated_shutdown_closes_idle_connections() {
+    use std::io::{Read, Write};
+
+    let mut app = create_test_app();
+    app.add_plugins(BevyWebServerPlugin)
+        .port_route(29187, "/ping", get(|| async { "pong" }))
+        .port_io_mode(29187, IoMode::Dedicated { threads: 1 });
+
+    let mut client = None;
+    let mut response = Vec::new();
+    let mut buffer = [0; 1024];
+    for _ in 0..500 {
+        app.update();
+        if client.is_none() {
+            client = std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, 29187)).ok();
+            if let Some(client) = &mut client {
+                client
+                    .set_read_timeout(Some(std::time::Duration::from_millis(5)))
+                    .unwrap();
+                client
+                    .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .unwrap();
+            }
+        }
+        if let Some(client) = &mut client {
+            if let Ok(read) = client.read(&mut buffer) {
+                response.extend_from_slice(&buffer[..read]);
+            }
+        }
+        // Last chunk of the chunked body
+        if response.ends_with(b"pong\r\n0\r\n\r\n") {
+            break;
+        }
+    }
+    assert!(
+        response.ends_with(b"pong\r\n0\r\n\r\n"),
+        "no response before shutdown"
+    );
+
+    // The client keeps its connection open, shutting down must not wait for it to leave
+    app.world_mut()
+        .resource_mut::<WebServerManager>()
+        .graceful_shutdown(&29187);
+    let client = client.as_mut().unwrap();
+    let mut closed = false;
+    for _ in 0..200 {
+        app.update();
+        if matches!(client.read(&mut buffer), Ok(0)) {
+            closed = true;
+            break;
+        }
+    }
+    assert!(closed, "idle connection was not closed on shutdown");
+}
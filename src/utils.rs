@@ -1,6 +1,7 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 /// Sanitize the file path to prevent directory traversal attacks
+#[deprecated(note = "use `normalize_path`, which rejects unsafe paths instead of rewriting them")]
 pub fn sanitize_path(path: &str) -> String {
     // Remove any ".." components and ensure we stay within our allowed directory
     let path = path.replace("..", "");
@@ -14,3 +15,195 @@ pub fn sanitize_path(path: &str) -> String {
     // Return the sanitized path as a string
     path_buf.to_string_lossy().to_string()
 }
+
+/// Decode `%XX` escapes, or `None` if they are malformed or don't decode to UTF-8
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Whether `segment` is a single plain file name on every platform
+fn is_safe_segment(segment: &str) -> bool {
+    if segment.contains(['\\', '/', '\0']) || has_drive_prefix(segment) {
+        return false;
+    }
+    let mut components = Path::new(segment).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// Whether `segment` starts like `C:`, which Windows reads as a drive
+fn has_drive_prefix(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Normalise a `/`-separated, already percent-decoded path below a root directory into a
+/// relative path, such as the one axum's `Path` extractor yields.
+///
+/// Empty and `.` segments are dropped and a leading `/` is ignored. Paths that could leave
+/// the root are rejected rather than rewritten: `..` segments, backslashes, drive letters and
+/// other prefixes, and NUL bytes. A `%` is an ordinary character here; use
+/// [`normalize_uri_path`] for paths still carrying their escapes.
+///
+/// ```rust
+/// use bevy_webgate::utils::normalize_path;
+///
+/// assert_eq!(normalize_path("/css/./a..b.css").unwrap(), std::path::Path::new("css/a..b.css"));
+/// assert_eq!(normalize_path("100%.png").unwrap(), std::path::Path::new("100%.png"));
+/// assert!(normalize_path("css/../../etc/passwd").is_none());
+/// ```
+pub fn normalize_path(path: &str) -> Option<PathBuf> {
+    normalize_segments(path, |segment| Some(segment.to_string()))
+}
+
+/// Like [`normalize_path`] for a raw URI path, decoding its `%XX` escapes first.
+///
+/// Unsafe segments hidden behind percent-encoding and malformed escapes are rejected too.
+///
+/// ```rust
+/// use bevy_webgate::utils::normalize_uri_path;
+///
+/// assert_eq!(normalize_uri_path("100%25.png").unwrap(), std::path::Path::new("100%.png"));
+/// assert!(normalize_uri_path("css/%2e%2e/%2e%2e/etc/passwd").is_none());
+/// ```
+pub fn normalize_uri_path(path: &str) -> Option<PathBuf> {
+    normalize_segments(path, percent_decode)
+}
+
+fn normalize_segments(path: &str, decode: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for segment in path.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        let decoded = decode(segment)?;
+        if !is_safe_segment(&decoded) {
+            return None;
+        }
+        normalized.push(decoded);
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOADS: [&str; 10] = [
+        "..",
+        "../etc/passwd",
+        "a/../../b",
+        "..\\secret",
+        "a\\..\\..\\b",
+        "C:\\Windows\\win.ini",
+        "C:/Windows/win.ini",
+        "c:",
+        "\\\\server\\share",
+        "file\0.txt",
+    ];
+
+    /// Unsafe only once their escapes are decoded
+    const ENCODED_PAYLOADS: [&str; 8] = [
+        "%2e%2e/secret",
+        "%2E%2e%2fsecret",
+        "..%2fsecret",
+        "..%5csecret",
+        "c%3a/boot.ini",
+        "file%00.txt",
+        "%zz",
+        "%",
+    ];
+
+    #[test]
+    fn test_rejects_traversal_payloads() {
+        for payload in PAYLOADS {
+            assert_eq!(normalize_path(payload), None, "accepted {payload:?}");
+            assert_eq!(normalize_uri_path(payload), None, "accepted {payload:?}");
+        }
+        for payload in ENCODED_PAYLOADS {
+            assert_eq!(normalize_uri_path(payload), None, "accepted {payload:?}");
+        }
+    }
+
+    #[test]
+    fn test_keeps_legitimate_names() {
+        assert_eq!(
+            normalize_path("a..b.txt").unwrap(),
+            PathBuf::from("a..b.txt")
+        );
+        assert_eq!(
+            normalize_path("//css/./site..min.css").unwrap(),
+            PathBuf::from("css/site..min.css")
+        );
+        assert_eq!(normalize_path("...").unwrap(), PathBuf::from("..."));
+        assert_eq!(normalize_path("").unwrap(), PathBuf::new());
+        assert_eq!(
+            normalize_path("notes/12:30.txt").unwrap(),
+            PathBuf::from("notes/12:30.txt")
+        );
+    }
+
+    /// Decoded paths keep their `%`, which is only an escape in raw URI paths
+    #[test]
+    fn test_literal_percent_names() {
+        for name in ["100%.png", "50%off.txt", "%2e%2e"] {
+            assert_eq!(normalize_path(name).unwrap(), PathBuf::from(name));
+        }
+        assert_eq!(
+            normalize_uri_path("sale/50%25off.txt").unwrap(),
+            PathBuf::from("sale/50%off.txt")
+        );
+    }
+
+    /// Random paths built from traversal fragments never escape the root
+    #[test]
+    fn test_random_paths_stay_below_root() {
+        const FRAGMENTS: [&str; 14] = [
+            "a", "b.txt", "..", ".", "", "%2e", "%2f", "%5c", "\\", ":", "%00", "c:", "a..b", "%",
+        ];
+        let mut rng = fastrand::Rng::with_seed(45);
+        for _ in 0..10_000 {
+            let path = (0..rng.usize(1..8))
+                .map(|_| {
+                    (0..rng.usize(1..3))
+                        .map(|_| FRAGMENTS[rng.usize(..FRAGMENTS.len())])
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+
+            for normalized in [normalize_path(&path), normalize_uri_path(&path)]
+                .into_iter()
+                .flatten()
+            {
+                for component in normalized.components() {
+                    let Component::Normal(name) = component else {
+                        panic!("{path:?} normalized to {normalized:?}");
+                    };
+                    let name = name.to_str().unwrap();
+                    assert!(
+                        name != ".."
+                            && !name.contains(['/', '\\', '\0'])
+                            && !has_drive_prefix(name),
+                        "accepted {path:?}"
+                    );
+                }
+            }
+        }
+    }
+}
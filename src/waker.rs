@@ -0,0 +1,161 @@
+use bevy_defer::{AsyncAccess, AsyncWorld};
+use bevy_ecs::prelude::*;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+/// Requests an app update whenever a server task becomes ready to make progress.
+///
+/// Server tasks run on the `bevy_defer` executor, which only advances when the app updates.
+/// Apps that update on demand, such as ones using `WinitSettings::desktop_app()`, insert this
+/// resource so incoming requests are served without waiting for the next user input.
+/// With the `winit` feature it is installed automatically from winit's event loop proxy.
+///
+/// ```rust,ignore
+/// app.insert_resource(WebServerWaker::new(move || {
+///     let _ = proxy.send_event(MyWakeUp);
+/// }));
+/// ```
+#[derive(Clone, Resource)]
+pub struct WebServerWaker(Arc<dyn Fn() + Send + Sync>);
+
+impl WebServerWaker {
+    pub fn new(wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self(Arc::new(wake))
+    }
+
+    /// Request an app update
+    pub fn wake(&self) {
+        (self.0)()
+    }
+
+    /// The waker of the current app, if one is installed
+    pub(crate) fn current() -> Option<Self> {
+        AsyncWorld
+            .resource::<WebServerWaker>()
+            .get(|waker| waker.clone())
+            .ok()
+    }
+}
+
+impl fmt::Debug for WebServerWaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("WebServerWaker").finish_non_exhaustive()
+    }
+}
+
+/// Wakes the task and requests an app update
+struct AppWaker {
+    task: Waker,
+    app: WebServerWaker,
+}
+
+impl Wake for AppWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.task.wake_by_ref();
+        self.app.wake();
+    }
+}
+
+/// Future that requests an app update whenever it is woken
+pub(crate) struct WakeApp<F> {
+    future: Pin<Box<F>>,
+    app: Option<WebServerWaker>,
+    /// Last task waker seen and the waker wrapping it, reused while the task waker is unchanged
+    waker: Option<(Waker, Waker)>,
+}
+
+impl<F: Future> WakeApp<F> {
+    pub(crate) fn new(future: F, app: Option<WebServerWaker>) -> Self {
+        Self {
+            future: Box::pin(future),
+            app,
+            waker: None,
+        }
+    }
+}
+
+impl<F: Future> Future for WakeApp<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.get_mut();
+        let Some(app) = &this.app else {
+            return this.future.as_mut().poll(cx);
+        };
+
+        let cached = this
+            .waker
+            .as_ref()
+            .is_some_and(|(task, _)| task.will_wake(cx.waker()));
+        if !cached {
+            let waker = Waker::from(Arc::new(AppWaker {
+                task: cx.waker().clone(),
+                app: app.clone(),
+            }));
+            this.waker = Some((cx.waker().clone(), waker));
+        }
+
+        let (_, waker) = this.waker.as_ref().expect("waker was just set");
+        this.future.as_mut().poll(&mut Context::from_waker(waker))
+    }
+}
+
+/// Run `future` so that it requests an app update whenever it can make progress
+pub(crate) async fn wake_app_on_progress<F: Future>(future: F) -> F::Output {
+    WakeApp::new(future, WebServerWaker::current()).await
+}
+
+/// Install a [`WebServerWaker`] sending winit's `WakeUp` event, unless one already exists
+#[cfg(feature = "winit")]
+pub(crate) fn install_winit_waker(
+    mut commands: Commands,
+    proxy: Option<Res<bevy_winit::EventLoopProxyWrapper<bevy_winit::WakeUp>>>,
+    existing: Option<Res<WebServerWaker>>,
+) {
+    if let (Some(proxy), None) = (proxy, existing) {
+        let proxy = std::sync::Mutex::new((**proxy).clone());
+        commands.insert_resource(WebServerWaker::new(move || {
+            if let Ok(proxy) = proxy.lock() {
+                let _ = proxy.send_event(bevy_winit::WakeUp);
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_wake_requests_app_update() {
+        let updates = Arc::new(AtomicUsize::new(0));
+        let app = WebServerWaker::new({
+            let updates = updates.clone();
+            move || {
+                updates.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        let (sender, receiver) = async_channel::bounded::<()>(1);
+        let mut future = Box::pin(WakeApp::new(
+            async move { receiver.recv().await },
+            Some(app),
+        ));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(updates.load(Ordering::Relaxed), 0);
+
+        sender.try_send(()).unwrap();
+        assert_eq!(updates.load(Ordering::Relaxed), 1);
+        assert!(future.as_mut().poll(&mut cx).is_ready());
+    }
+}
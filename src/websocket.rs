@@ -0,0 +1,684 @@
+use async_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bevy_app::{App, Plugin, Update};
+use bevy_defer::{AccessError, AsyncAccess, AsyncExecutor, AsyncWorld};
+use bevy_ecs::prelude::*;
+use bevy_log::{debug, error, warn};
+use futures_util::{future, io, SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::task::{ready, Context, Poll};
+
+use crate::server::{IoExecutor, TaskType};
+use crate::waker::wake_app_on_progress;
+use crate::{HttpErrorResponses, WebPort, WebServerManager};
+
+pub struct WebSocketPlugin;
+
+impl Plugin for WebSocketPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WebSocketOpened>()
+            .add_event::<WebSocketReceived>()
+            .add_event::<WebSocketClosed>()
+            .add_event::<WebSocketSend>()
+            .init_resource::<ClosedWebSockets>()
+            .add_systems(
+                Update,
+                (
+                    forward_websocket_sends,
+                    close_websockets_on_shutdown,
+                    despawn_closed_websockets,
+                ),
+            );
+    }
+}
+
+/// Buffering limits of the sockets of a WebSocket route
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WebSocketConfig {
+    /// Messages queued for a client before it is considered too slow and disconnected
+    pub send_buffer: usize,
+    /// Received messages queued before the socket stops reading until the app catches up
+    pub receive_buffer: usize,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            send_buffer: 64,
+            receive_buffer: 64,
+        }
+    }
+}
+
+impl WebSocketConfig {
+    pub fn with_send_buffer(mut self, send_buffer: usize) -> Self {
+        self.send_buffer = send_buffer.max(1);
+        self
+    }
+
+    pub fn with_receive_buffer(mut self, receive_buffer: usize) -> Self {
+        self.receive_buffer = receive_buffer.max(1);
+        self
+    }
+}
+
+/// A message exchanged over a WebSocket
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl From<WebSocketMessage> for Message {
+    fn from(message: WebSocketMessage) -> Self {
+        match message {
+            WebSocketMessage::Text(text) => Message::text(text),
+            WebSocketMessage::Binary(data) => Message::binary(data),
+        }
+    }
+}
+
+/// Component on the entity representing an open WebSocket.
+///
+/// Despawning the entity closes the socket.
+#[derive(Component, Debug)]
+pub struct WebSocketConnection {
+    port: WebPort,
+    path: String,
+    peer: Option<SocketAddr>,
+    sender: async_channel::Sender<WebSocketMessage>,
+    /// Aborts the socket without flushing the queue, once the client fell too far behind
+    abort: async_channel::Sender<()>,
+}
+
+impl WebSocketConnection {
+    pub fn port(&self) -> WebPort {
+        self.port
+    }
+
+    /// Route path the socket was opened on
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Queue a message for the client, returns `false` if the socket is already closed.
+    ///
+    /// A client whose queue is full is too slow to keep up and gets disconnected.
+    pub fn send(&self, message: WebSocketMessage) -> bool {
+        match self.sender.try_send(message) {
+            Ok(()) => true,
+            Err(async_channel::TrySendError::Full(_)) => {
+                warn!(
+                    "WebSocket send queue of {:?} on port {} is full, disconnecting it",
+                    self.peer, self.port
+                );
+                self.sender.close();
+                let _ = self.abort.try_send(());
+                false
+            }
+            Err(async_channel::TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+/// Sent once the WebSocket handshake completed and the entity is ready
+#[derive(Clone, Debug, Event)]
+pub struct WebSocketOpened {
+    pub entity: Entity,
+}
+
+/// Sent for every text or binary message received from a client
+#[derive(Clone, Debug, Event)]
+pub struct WebSocketReceived {
+    pub entity: Entity,
+    pub message: WebSocketMessage,
+}
+
+/// Sent when a socket closed, also when its server stopped; its entity has been despawned by then
+#[derive(Clone, Debug, Event)]
+pub struct WebSocketClosed {
+    pub entity: Entity,
+}
+
+/// Write this event to send a message to the client behind `entity`
+#[derive(Clone, Debug, Event)]
+pub struct WebSocketSend {
+    pub entity: Entity,
+    pub message: WebSocketMessage,
+}
+
+fn forward_websocket_sends(
+    mut events: EventReader<WebSocketSend>,
+    connections: Query<&WebSocketConnection>,
+) {
+    for event in events.read() {
+        match connections.get(event.entity) {
+            Ok(connection) => {
+                if !connection.send(event.message.clone()) {
+                    debug!("WebSocket {} is closed, dropping message", event.entity);
+                }
+            }
+            Err(_) => warn!("No WebSocket connection on entity {}", event.entity),
+        }
+    }
+}
+
+/// Close the sockets of servers shutting down gracefully, with a Close frame once
+/// their queued messages are sent
+fn close_websockets_on_shutdown(
+    connections: Query<&WebSocketConnection>,
+    manager: Res<WebServerManager>,
+) {
+    for connection in &connections {
+        if manager.shutdown_requested(&connection.port) {
+            connection.sender.close();
+        }
+    }
+}
+
+/// Entities of sockets whose task ended, or was cancelled because its server stopped
+#[derive(Resource)]
+struct ClosedWebSockets {
+    sender: async_channel::Sender<Entity>,
+    receiver: async_channel::Receiver<Entity>,
+}
+
+impl Default for ClosedWebSockets {
+    fn default() -> Self {
+        let (sender, receiver) = async_channel::unbounded();
+        Self { sender, receiver }
+    }
+}
+
+/// Owned by a socket's task, reports the socket closed however the task ends
+struct CloseOnDrop {
+    entity: Entity,
+    closed: async_channel::Sender<Entity>,
+}
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        let _ = self.closed.try_send(self.entity);
+    }
+}
+
+fn despawn_closed_websockets(world: &mut World) {
+    let closed = world.resource::<ClosedWebSockets>().receiver.clone();
+    while let Ok(entity) = closed.try_recv() {
+        if let Ok(entity_mut) = world.get_entity_mut(entity) {
+            entity_mut.despawn();
+        }
+        world.send_event(WebSocketClosed { entity });
+    }
+}
+
+/// Handle a WebSocket upgrade request for a route registered with
+/// [`WebServerAppExt::port_websocket`](crate::WebServerAppExt::port_websocket)
+pub(crate) async fn websocket_upgrade(
+    port: WebPort,
+    path: String,
+    config: WebSocketConfig,
+    mut request: Request,
+) -> Response {
+    let Some(accept_key) = handshake_accept_key(request.headers()) else {
+        return HttpErrorResponses::respond(StatusCode::BAD_REQUEST);
+    };
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| *peer);
+    let io = request.extensions().get::<IoExecutor>().cloned();
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    let (sender, outgoing) = async_channel::bounded(config.send_buffer.max(1));
+    let (abort, aborted) = async_channel::bounded(1);
+
+    let (entity, closed) = AsyncWorld.run(|world| {
+        let entity = world
+            .spawn(WebSocketConnection {
+                port,
+                path,
+                peer,
+                sender,
+                abort,
+            })
+            .id();
+        (entity, world.resource::<ClosedWebSockets>().sender.clone())
+    });
+
+    let spawned = AsyncWorld
+        .non_send_resource::<AsyncExecutor>()
+        .get(|executor| executor.clone())
+        .and_then(|executor| {
+            let task = executor.spawn_task(wake_app_on_progress(async move {
+                // Also dropped when stopping the server cancels this task
+                let _close = CloseOnDrop { entity, closed };
+                match on_upgrade.await {
+                    Ok(upgraded) => {
+                        let stream = WebSocketStream::from_raw_socket(
+                            UpgradedIo(upgraded),
+                            Role::Server,
+                            None,
+                        )
+                        .await;
+                        AsyncWorld.run(|world| {
+                            world.send_event(WebSocketOpened { entity });
+                        });
+
+                        let (received, incoming) =
+                            async_channel::bounded(config.receive_buffer.max(1));
+                        let pump = pump_websocket(entity, stream, outgoing, received, aborted);
+                        let forward = async {
+                            while let Ok(message) = incoming.recv().await {
+                                AsyncWorld.run(|world| {
+                                    world.send_event(WebSocketReceived { entity, message });
+                                });
+                            }
+                        };
+                        // Sockets of dedicated I/O servers are driven on their I/O threads,
+                        // only the events are sent from the app's executor
+                        match io {
+                            // `None` once the I/O threads shut down, closing the socket
+                            Some(io) => {
+                                if let Some(pump) = io.spawn(pump) {
+                                    future::join(pump, forward).await;
+                                }
+                            }
+                            None => {
+                                future::join(pump, forward).await;
+                            }
+                        }
+                    }
+                    Err(err) => debug!("WebSocket upgrade for {} failed: {}", entity, err),
+                }
+
+                AsyncWorld
+                    .resource::<WebServerManager>()
+                    .get_mut(|manager| {
+                        if let Some(server) = manager.get_server_mut(&port) {
+                            server.task_store_mut().remove(&TaskType::WebSocket(entity));
+                        }
+                    })?;
+
+                Ok(())
+            }));
+
+            AsyncWorld
+                .resource::<WebServerManager>()
+                .get_mut(|manager| {
+                    let Some(server) = manager.get_server_mut(&port) else {
+                        return Err(AccessError::Custom("No server found on port"));
+                    };
+                    server
+                        .task_store_mut()
+                        .insert(TaskType::WebSocket(entity), task);
+                    Ok(())
+                })?
+        });
+
+    if let Err(err) = spawned {
+        error!("Failed to spawn WebSocket task on port {}: {}", port, err);
+        AsyncWorld.run(|world| {
+            world.despawn(entity);
+        });
+        return HttpErrorResponses::respond(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    (
+        StatusCode::SWITCHING_PROTOCOLS,
+        [
+            (header::CONNECTION, "upgrade".to_string()),
+            (header::UPGRADE, "websocket".to_string()),
+            (header::SEC_WEBSOCKET_ACCEPT, accept_key),
+        ],
+    )
+        .into_response()
+}
+
+/// Validate a WebSocket handshake request and compute the `Sec-WebSocket-Accept` value
+fn handshake_accept_key(headers: &HeaderMap) -> Option<String> {
+    let header_contains = |name: header::HeaderName, token: &str| {
+        headers.get_all(name).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .any(|part| part.trim().eq_ignore_ascii_case(token))
+            })
+        })
+    };
+
+    if !header_contains(header::CONNECTION, "upgrade")
+        || !header_contains(header::UPGRADE, "websocket")
+        || headers.get(header::SEC_WEBSOCKET_VERSION)? != "13"
+    {
+        return None;
+    }
+
+    let key = headers.get(header::SEC_WEBSOCKET_KEY)?;
+    Some(derive_accept_key(key.as_bytes()))
+}
+
+/// Pump messages between the socket and the app's channels until either side closes,
+/// or until `aborted` is signalled because the client fell behind
+async fn pump_websocket(
+    entity: Entity,
+    stream: WebSocketStream<UpgradedIo>,
+    outgoing: async_channel::Receiver<WebSocketMessage>,
+    received: async_channel::Sender<WebSocketMessage>,
+    aborted: async_channel::Receiver<()>,
+) {
+    let (mut sink, mut stream) = stream.split();
+
+    let incoming = async {
+        while let Some(message) = stream.next().await {
+            let message = match message {
+                Ok(Message::Text(text)) => WebSocketMessage::Text(text.to_string()),
+                Ok(Message::Binary(data)) => WebSocketMessage::Binary(data.to_vec()),
+                Ok(Message::Close(_)) => break,
+                // Pings are answered by tungstenite itself
+                Ok(_) => continue,
+                Err(err) => {
+                    debug!("WebSocket {} read error: {}", entity, err);
+                    break;
+                }
+            };
+            // Waits while the app is behind, which stops reading from the client
+            if received.send(message).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let outgoing = async {
+        // Ends once the connection component, and with it the sender, is dropped
+        while let Ok(message) = outgoing.recv().await {
+            if let Err(err) = sink.send(message.into()).await {
+                debug!("WebSocket {} write error: {}", entity, err);
+                return;
+            }
+        }
+        let _ = sink.close().await;
+    };
+
+    let abort = async {
+        // The sender is dropped along with the connection, which closes the socket normally
+        if aborted.recv().await.is_err() {
+            future::pending::<()>().await;
+        }
+        debug!("WebSocket {} fell behind, aborting it", entity);
+    };
+
+    let (incoming, outgoing, abort) = (pin!(incoming), pin!(outgoing), pin!(abort));
+    future::select(future::select(incoming, outgoing), abort).await;
+}
+
+/// Adapts hyper's upgraded connection to the `futures-io` traits used by async-tungstenite
+struct UpgradedIo(Upgraded);
+
+impl io::AsyncRead for UpgradedIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut read_buf = hyper::rt::ReadBuf::new(buf);
+        ready!(hyper::rt::Read::poll_read(
+            Pin::new(&mut self.0),
+            cx,
+            read_buf.unfilled()
+        ))?;
+        Poll::Ready(Ok(read_buf.filled().len()))
+    }
+}
+
+impl io::AsyncWrite for UpgradedIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        hyper::rt::Write::poll_write(Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        hyper::rt::Write::poll_flush(Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        hyper::rt::Write::poll_shutdown(Pin::new(&mut self.0), cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BevyWebServerPlugin, IoMode, WebServerAppExt};
+    use async_io::Async;
+    use axum::http::HeaderValue;
+    use std::net::{Ipv4Addr, TcpStream};
+    use std::time::Duration;
+
+    /// Answer every message with its text in upper case
+    fn echo_upper_case(
+        mut received: EventReader<WebSocketReceived>,
+        mut send: EventWriter<WebSocketSend>,
+    ) {
+        for event in received.read() {
+            if let WebSocketMessage::Text(text) = &event.message {
+                send.write(WebSocketSend {
+                    entity: event.entity,
+                    message: WebSocketMessage::Text(text.to_uppercase()),
+                });
+            }
+        }
+    }
+
+    /// Send `hello` through a socket on `port` and return the app's answer
+    fn echo_through_app(port: WebPort, io_mode: IoMode) -> String {
+        let mut app = App::new();
+        app.add_plugins((bevy::MinimalPlugins, BevyWebServerPlugin))
+            .add_systems(Update, echo_upper_case)
+            .port_websocket(port, "/ws")
+            .port_io_mode(port, io_mode);
+
+        let (answer, answered) = async_channel::bounded(1);
+        let mut client = None;
+        for _ in 0..1000 {
+            app.update();
+            if client.is_none() && TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_ok() {
+                let answer = answer.clone();
+                client = Some(std::thread::spawn(move || {
+                    async_io::block_on(async move {
+                        let stream = Async::<TcpStream>::connect((Ipv4Addr::LOCALHOST, port))
+                            .await
+                            .unwrap();
+                        let url = format!("ws://localhost:{port}/ws");
+                        let (mut socket, _) =
+                            async_tungstenite::client_async(url, stream).await.unwrap();
+                        socket.send(Message::text("hello")).await.unwrap();
+                        let reply = socket.next().await.unwrap().unwrap();
+                        answer
+                            .send(reply.into_text().unwrap().to_string())
+                            .await
+                            .unwrap();
+                    })
+                }));
+            }
+            if let Ok(answer) = answered.try_recv() {
+                return answer;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        panic!("no answer through the WebSocket on port {port}");
+    }
+
+    #[test]
+    fn test_messages_pass_through_events() {
+        assert_eq!(echo_through_app(29188, IoMode::Main), "HELLO");
+        assert_eq!(
+            echo_through_app(29189, IoMode::Dedicated { threads: 1 }),
+            "HELLO"
+        );
+    }
+
+    #[derive(Default, Resource)]
+    struct SocketEvents {
+        opened: Vec<Entity>,
+        closed: Vec<Entity>,
+    }
+
+    fn record_socket_events(
+        mut opened: EventReader<WebSocketOpened>,
+        mut closed: EventReader<WebSocketClosed>,
+        mut events: ResMut<SocketEvents>,
+    ) {
+        events
+            .opened
+            .extend(opened.read().map(|event| event.entity));
+        events
+            .closed
+            .extend(closed.read().map(|event| event.entity));
+    }
+
+    /// Open a socket on `port`, then `stop` its server; returns whether the client got a Close frame
+    fn stop_with_open_socket(port: WebPort, stop: fn(&mut WebServerManager, &WebPort)) -> bool {
+        let mut app = App::new();
+        app.add_plugins((bevy::MinimalPlugins, BevyWebServerPlugin))
+            .init_resource::<SocketEvents>()
+            .add_systems(Update, record_socket_events)
+            .port_websocket(port, "/ws");
+
+        let (ended, end) = async_channel::bounded(1);
+        let mut client = None;
+        let mut socket = None;
+        let mut close_frame = None;
+        for _ in 0..1000 {
+            app.update();
+            if client.is_none() && TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_ok() {
+                let ended = ended.clone();
+                client = Some(std::thread::spawn(move || {
+                    async_io::block_on(async move {
+                        let stream = Async::<TcpStream>::connect((Ipv4Addr::LOCALHOST, port))
+                            .await
+                            .unwrap();
+                        let url = format!("ws://localhost:{port}/ws");
+                        let (mut socket, _) =
+                            async_tungstenite::client_async(url, stream).await.unwrap();
+                        let close_frame =
+                            matches!(socket.next().await, Some(Ok(Message::Close(_))));
+                        ended.send(close_frame).await.unwrap();
+                    })
+                }));
+            }
+            if socket.is_none() {
+                socket = app
+                    .world()
+                    .resource::<SocketEvents>()
+                    .opened
+                    .first()
+                    .copied();
+                if socket.is_some() {
+                    stop(
+                        &mut app.world_mut().resource_mut::<WebServerManager>(),
+                        &port,
+                    );
+                }
+            }
+            if let Ok(ended) = end.try_recv() {
+                close_frame = Some(ended);
+            }
+            if let (Some(socket), Some(close_frame)) = (socket, close_frame) {
+                if app
+                    .world()
+                    .resource::<SocketEvents>()
+                    .closed
+                    .contains(&socket)
+                {
+                    assert!(app.world().get_entity(socket).is_err());
+                    return close_frame;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        panic!("the WebSocket on port {port} was not closed");
+    }
+
+    #[test]
+    fn test_stopping_server_closes_sockets() {
+        stop_with_open_socket(29192, WebServerManager::stop_server);
+        // A graceful shutdown closes them properly
+        assert!(stop_with_open_socket(
+            29193,
+            WebServerManager::graceful_shutdown
+        ));
+    }
+
+    #[test]
+    fn test_full_send_queue_disconnects() {
+        let (sender, _outgoing) = async_channel::bounded(1);
+        let (abort, aborted) = async_channel::bounded(1);
+        let connection = WebSocketConnection {
+            port: 0,
+            path: "/ws".to_string(),
+            peer: None,
+            sender,
+            abort,
+        };
+
+        assert!(connection.send(WebSocketMessage::Text("first".to_string())));
+        assert!(!connection.send(WebSocketMessage::Text("second".to_string())));
+        assert!(aborted.try_recv().is_ok());
+        assert!(!connection.send(WebSocketMessage::Text("third".to_string())));
+    }
+
+    fn handshake_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("keep-alive, Upgrade"),
+        );
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(
+            header::SEC_WEBSOCKET_VERSION,
+            HeaderValue::from_static("13"),
+        );
+        headers.insert(
+            header::SEC_WEBSOCKET_KEY,
+            HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_handshake_accept_key() {
+        // Example handshake from RFC 6455, section 1.3
+        assert_eq!(
+            handshake_accept_key(&handshake_headers()).as_deref(),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+    }
+
+    #[test]
+    fn test_handshake_rejects_plain_requests() {
+        let mut headers = handshake_headers();
+        headers.remove(header::UPGRADE);
+        assert!(handshake_accept_key(&headers).is_none());
+
+        let mut headers = handshake_headers();
+        headers.insert(header::SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        assert!(handshake_accept_key(&headers).is_none());
+    }
+}